@@ -0,0 +1,447 @@
+use std::fmt;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// An X.509 certificate presented by a peer, with the fields most useful
+/// for logging and alerting already decoded.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PeerCertificate {
+    der: Vec<u8>,
+    subject: String,
+    issuer: String,
+    not_before: SystemTime,
+    not_after: SystemTime,
+}
+
+impl PeerCertificate {
+    /// Decode a DER-encoded certificate.
+    pub fn from_der(der: &[u8]) -> Result<PeerCertificate, io::Error> {
+        parse(der).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "malformed X.509 certificate")
+        })
+    }
+
+    /// The certificate as DER.
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// The subject name, such as `CN=example.com, O=Example`.
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// The issuer name, in the same format as `subject`.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The start of the validity period.
+    pub fn not_before(&self) -> SystemTime {
+        self.not_before
+    }
+
+    /// The end of the validity period.
+    pub fn not_after(&self) -> SystemTime {
+        self.not_after
+    }
+}
+
+impl fmt::Debug for PeerCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PeerCertificate")
+            .field("subject", &self.subject)
+            .field("issuer", &self.issuer)
+            .field("not_before", &self.not_before)
+            .field("not_after", &self.not_after)
+            .finish()
+    }
+}
+
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_BIT_STRING: u8 = 0x03;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_TELETEX_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_BMP_STRING: u8 = 0x1e;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_CONTEXT_0: u8 = 0xa0;
+const TAG_CONTEXT_1: u8 = 0xa1;
+const TAG_CONTEXT_3: u8 = 0xa3;
+const TAG_IMPLICIT_1: u8 = 0x81;
+const TAG_IMPLICIT_2: u8 = 0x82;
+
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Reads DER elements with single-byte tags, which is all X.509 needs.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn peek_tag(&self) -> Option<u8> {
+        self.0.first().cloned()
+    }
+
+    /// Reads the next element, returning its tag and contents.
+    fn any(&mut self) -> Option<(u8, &'a [u8])> {
+        let (tag, header, len) = self.header()?;
+        let end = header.checked_add(len)?;
+        let element = self.0.get(header..end)?;
+        self.0 = &self.0[end..];
+        Some((tag, element))
+    }
+
+    /// Reads the next element including its header, as it appears on the
+    /// wire.
+    fn raw(&mut self) -> Option<&'a [u8]> {
+        let (_, header, len) = self.header()?;
+        let end = header.checked_add(len)?;
+        let raw = self.0.get(..end)?;
+        self.0 = &self.0[end..];
+        Some(raw)
+    }
+
+    /// Reads the next element, which must have tag `tag`.
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.any()? {
+            (t, contents) if t == tag => Some(contents),
+            _ => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn header(&self) -> Option<(u8, usize, usize)> {
+        let tag = *self.0.first()?;
+        let first = *self.0.get(1)? as usize;
+        if first < 0x80 {
+            return Some((tag, 2, first));
+        }
+        let octets = first & 0x7f;
+        if octets == 0 || octets > 4 {
+            return None;
+        }
+        let bytes = self.0.get(2..2 + octets)?;
+        // DER lengths are minimal: no leading zero octet, and the long form
+        // only for lengths that do not fit the short one.
+        if bytes[0] == 0 {
+            return None;
+        }
+        let len = bytes.iter().fold(0u64, |len, &b| len << 8 | u64::from(b));
+        if len < 0x80 || len > usize::MAX as u64 {
+            return None;
+        }
+        Some((tag, 2 + octets, len as usize))
+    }
+}
+
+/// The contents of `der`, which must be a single SEQUENCE.
+fn sequence(der: &[u8]) -> Option<Der<'_>> {
+    let mut outer = Der(der);
+    let contents = outer.expect(TAG_SEQUENCE)?;
+    if !outer.is_empty() {
+        return None;
+    }
+    Some(Der(contents))
+}
+
+/// The to-be-signed part of a certificate, positioned after the serial
+/// number and signature algorithm.
+fn tbs_certificate(der: &[u8]) -> Option<Der<'_>> {
+    let mut tbs = Der(sequence(der)?.expect(TAG_SEQUENCE)?);
+    if tbs.peek_tag() == Some(TAG_VERSION) {
+        tbs.any()?;
+    }
+    tbs.expect(TAG_INTEGER)?;
+    tbs.expect(TAG_SEQUENCE)?;
+    Some(tbs)
+}
+
+/// The DER-encoded SubjectPublicKeyInfo of a certificate.
+pub(crate) fn subject_public_key_info(der: &[u8]) -> Option<&[u8]> {
+    let mut tbs = tbs_certificate(der)?;
+    tbs.expect(TAG_SEQUENCE)?;
+    tbs.expect(TAG_SEQUENCE)?;
+    tbs.expect(TAG_SEQUENCE)?;
+    tbs.raw()
+}
+
+/// The name a DER certificate is for, to refer to it by in messages: its
+/// subject's common name, else the first DNS name among its alternative
+/// names.
+pub(crate) fn display_name(der: &[u8]) -> Option<String> {
+    let mut tbs = tbs_certificate(der)?;
+    tbs.expect(TAG_SEQUENCE)?;
+    tbs.expect(TAG_SEQUENCE)?;
+    let common_name = attributes(tbs.expect(TAG_SEQUENCE)?)?
+        .into_iter()
+        .find(|&(oid, _)| oid == OID_COMMON_NAME)
+        .and_then(|(_, (tag, value))| string_value(tag, value));
+    if common_name.is_some() {
+        return common_name;
+    }
+
+    tbs.expect(TAG_SEQUENCE)?;
+    while !tbs.is_empty() {
+        let (tag, contents) = tbs.any()?;
+        if tag != TAG_CONTEXT_3 {
+            continue;
+        }
+        let mut extensions = sequence(contents)?;
+        while !extensions.is_empty() {
+            let mut extension = Der(extensions.expect(TAG_SEQUENCE)?);
+            if extension.expect(TAG_OID)? != OID_SUBJECT_ALT_NAME {
+                continue;
+            }
+            if extension.peek_tag() == Some(TAG_BOOLEAN) {
+                extension.any()?;
+            }
+            let mut names = sequence(extension.expect(TAG_OCTET_STRING)?)?;
+            while !names.is_empty() {
+                if let (TAG_IMPLICIT_2, name) = names.any()? {
+                    return String::from_utf8(name.to_vec()).ok();
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Whether a DER private key, from a PEM block labelled `label`, belongs
+/// with a DER certificate, or `None` if that cannot be told without doing
+/// the key's arithmetic, as for encrypted keys.
+pub(crate) fn key_matches(certificate: &[u8], label: &str, key: &[u8]) -> Option<bool> {
+    let mut spki = sequence(subject_public_key_info(certificate)?)?;
+    spki.expect(TAG_SEQUENCE)?;
+    let public_key = spki.expect(TAG_BIT_STRING)?.get(1..)?;
+    let from_key = match label {
+        "PRIVATE KEY" => pkcs8_public_key(key)?,
+        "RSA PRIVATE KEY" => rsa_public_key(key)?,
+        "EC PRIVATE KEY" => ec_public_key(key)?,
+        _ => return None,
+    };
+    Some(from_key == public_key)
+}
+
+/// The public key in a PKCS#8 PrivateKeyInfo or OneAsymmetricKey.
+fn pkcs8_public_key(der: &[u8]) -> Option<Vec<u8>> {
+    let mut info = sequence(der)?;
+    info.expect(TAG_INTEGER)?;
+    let algorithm = Der(info.expect(TAG_SEQUENCE)?).expect(TAG_OID)?;
+    let private_key = info.expect(TAG_OCTET_STRING)?;
+    if info.peek_tag() == Some(TAG_CONTEXT_0) {
+        info.any()?;
+    }
+    if info.peek_tag() == Some(TAG_IMPLICIT_1) {
+        return info.any()?.1.get(1..).map(|k| k.to_vec());
+    }
+    match algorithm {
+        OID_RSA_ENCRYPTION => rsa_public_key(private_key),
+        OID_EC_PUBLIC_KEY => ec_public_key(private_key),
+        _ => None,
+    }
+}
+
+/// The RSAPublicKey of a PKCS#1 RSAPrivateKey.
+fn rsa_public_key(der: &[u8]) -> Option<Vec<u8>> {
+    let mut key = sequence(der)?;
+    key.expect(TAG_INTEGER)?;
+    let modulus = key.raw()?;
+    let exponent = key.raw()?;
+    let len = modulus.len() + exponent.len();
+    let mut public_key = vec![TAG_SEQUENCE];
+    if len < 0x80 {
+        public_key.push(len as u8);
+    } else {
+        let octets: Vec<u8> = (0..4)
+            .rev()
+            .map(|i| (len >> (8 * i)) as u8)
+            .skip_while(|&b| b == 0)
+            .collect();
+        public_key.push(0x80 | octets.len() as u8);
+        public_key.extend_from_slice(&octets);
+    }
+    public_key.extend_from_slice(modulus);
+    public_key.extend_from_slice(exponent);
+    Some(public_key)
+}
+
+/// The public point of a SEC 1 ECPrivateKey, which is optional.
+fn ec_public_key(der: &[u8]) -> Option<Vec<u8>> {
+    let mut key = sequence(der)?;
+    key.expect(TAG_INTEGER)?;
+    key.expect(TAG_OCTET_STRING)?;
+    if key.peek_tag() == Some(TAG_CONTEXT_0) {
+        key.any()?;
+    }
+    let public_key = Der(key.expect(TAG_CONTEXT_1)?).expect(TAG_BIT_STRING)?;
+    public_key.get(1..).map(|k| k.to_vec())
+}
+
+fn parse(der: &[u8]) -> Option<PeerCertificate> {
+    let mut tbs = tbs_certificate(der)?;
+    let issuer = name(tbs.expect(TAG_SEQUENCE)?)?;
+    let mut validity = Der(tbs.expect(TAG_SEQUENCE)?);
+    let not_before = time(validity.any()?)?;
+    let not_after = time(validity.any()?)?;
+    let subject = name(tbs.expect(TAG_SEQUENCE)?)?;
+
+    Some(PeerCertificate {
+        der: der.to_vec(),
+        subject,
+        issuer,
+        not_before,
+        not_after,
+    })
+}
+
+const ATTRIBUTE_NAMES: &[(&[u8], &str)] = &[
+    (&[0x55, 0x04, 0x03], "CN"),
+    (&[0x55, 0x04, 0x05], "serialNumber"),
+    (&[0x55, 0x04, 0x06], "C"),
+    (&[0x55, 0x04, 0x07], "L"),
+    (&[0x55, 0x04, 0x08], "ST"),
+    (&[0x55, 0x04, 0x09], "street"),
+    (&[0x55, 0x04, 0x0a], "O"),
+    (&[0x55, 0x04, 0x0b], "OU"),
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x01],
+        "emailAddress",
+    ),
+    (
+        &[0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x19],
+        "DC",
+    ),
+];
+
+fn oid_to_string(oid: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    if let Some(&first) = oid.first() {
+        arcs.push(u64::from(first / 40));
+        arcs.push(u64::from(first % 40));
+    }
+    let mut arc = 0u64;
+    for &b in oid.iter().skip(1) {
+        arc = arc << 7 | u64::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    let arcs: Vec<String> = arcs.iter().map(|a| a.to_string()).collect();
+    arcs.join(".")
+}
+
+fn string_value(tag: u8, value: &[u8]) -> Option<String> {
+    match tag {
+        TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING => {
+            String::from_utf8(value.to_vec()).ok()
+        }
+        TAG_TELETEX_STRING => Some(value.iter().map(|&b| b as char).collect()),
+        TAG_BMP_STRING => {
+            let units: Vec<u16> = value
+                .chunks(2)
+                .map(|c| u16::from(c[0]) << 8 | u16::from(*c.get(1).unwrap_or(&0)))
+                .collect();
+            String::from_utf16(&units).ok()
+        }
+        _ => None,
+    }
+}
+
+/// An attribute of a distinguished name: its OID and tagged value.
+type Attribute<'a> = (&'a [u8], (u8, &'a [u8]));
+
+/// The attributes of a distinguished name in encoded order.
+fn attributes(rdns: &[u8]) -> Option<Vec<Attribute<'_>>> {
+    let mut attributes = Vec::new();
+    let mut rdns = Der(rdns);
+    while !rdns.is_empty() {
+        let mut set = Der(rdns.expect(TAG_SET)?);
+        while !set.is_empty() {
+            let mut attribute = Der(set.expect(TAG_SEQUENCE)?);
+            let oid = attribute.expect(TAG_OID)?;
+            attributes.push((oid, attribute.any()?));
+        }
+    }
+    Some(attributes)
+}
+
+/// Renders a distinguished name as `TYPE=value` pairs in encoded order.
+fn name(rdns: &[u8]) -> Option<String> {
+    let parts: Vec<String> = attributes(rdns)?
+        .into_iter()
+        .map(|(oid, (tag, value))| {
+            let key = ATTRIBUTE_NAMES
+                .iter()
+                .find(|&&(known, _)| known == oid)
+                .map(|&(_, short)| short.to_owned())
+                .unwrap_or_else(|| oid_to_string(oid));
+            let value = string_value(tag, value).unwrap_or_else(|| "<binary>".to_owned());
+            format!("{}={}", key, value)
+        })
+        .collect();
+    Some(parts.join(", "))
+}
+
+fn digits(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || !s.iter().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(s.iter().fold(0, |n, &b| n * 10 + u64::from(b - b'0')))
+}
+
+/// Days between 1970-01-01 and the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Decodes an X.509 `Time`, which is UTCTime or GeneralizedTime in UTC.
+fn time((tag, value): (u8, &[u8])) -> Option<SystemTime> {
+    let (year, rest) = match tag {
+        TAG_UTC_TIME => {
+            let yy = digits(value.get(..2)?)?;
+            (if yy < 50 { 2000 + yy } else { 1900 + yy }, &value[2..])
+        }
+        TAG_GENERALIZED_TIME => (digits(value.get(..4)?)?, &value[4..]),
+        _ => return None,
+    };
+    if rest.len() != 11 || rest[10] != b'Z' {
+        return None;
+    }
+    let field = |i: usize| digits(&rest[i..i + 2]);
+    let (month, day) = (field(0)?, field(2)?);
+    let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year as i64, month as i64, day as i64);
+    let secs = days * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+    if secs >= 0 {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        Some(UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()))
+    }
+}
@@ -0,0 +1,347 @@
+use base64;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use tls_api::{self, TlsConnectorBuilder};
+
+use certificate;
+
+/// A certificate and its private key, presented by a client to servers that
+/// ask for one (mutual TLS), or by a server to its clients.
+///
+/// The bytes are handed to the TLS backend as they are, through
+/// `ClientTlsHooks::on_set_identity` or `ServerTlsHooks`.
+#[derive(Clone)]
+pub enum Identity {
+    /// A DER-encoded PKCS#12 archive and the password protecting it.
+    Pkcs12 { der: Vec<u8>, password: String },
+    /// A PEM certificate chain, leaf first, and a PEM private key.
+    Pem {
+        certificate_chain: Vec<u8>,
+        private_key: Vec<u8>,
+    },
+}
+
+impl Identity {
+    /// An identity from a DER-encoded PKCS#12 archive, as produced by
+    /// `openssl pkcs12 -export`.
+    pub fn from_pkcs12(der: &[u8], password: &str) -> Identity {
+        Identity::Pkcs12 {
+            der: der.to_vec(),
+            password: password.to_owned(),
+        }
+    }
+
+    /// An identity from a PEM certificate chain and private key.
+    ///
+    /// Fails if `certificate_chain` has no `CERTIFICATE` block or
+    /// `private_key` has no `PRIVATE KEY` block.
+    pub fn from_pem(certificate_chain: &[u8], private_key: &[u8]) -> Result<Identity, io::Error> {
+        if !pem_blocks(certificate_chain)?
+            .iter()
+            .any(|(label, _)| label == "CERTIFICATE")
+        {
+            return Err(invalid("no certificate in the PEM certificate chain"));
+        }
+        if !pem_blocks(private_key)?
+            .iter()
+            .any(|(label, _)| label.ends_with("PRIVATE KEY"))
+        {
+            return Err(invalid("no private key in the PEM private key"));
+        }
+        Ok(Identity::Pem {
+            certificate_chain: certificate_chain.to_vec(),
+            private_key: private_key.to_vec(),
+        })
+    }
+
+    /// Whether the private key belongs with the leaf certificate, or `None`
+    /// if that cannot be told without the backend, as for PKCS#12 archives
+    /// and encrypted keys.
+    pub(crate) fn key_matches_certificate(&self) -> Option<bool> {
+        match *self {
+            Identity::Pkcs12 { .. } => None,
+            Identity::Pem {
+                ref private_key, ..
+            } => {
+                let leaf = self.pem_leaf()?;
+                let blocks = pem_blocks(private_key).ok()?;
+                let (label, key) = blocks
+                    .iter()
+                    .find(|(label, _)| label.ends_with("PRIVATE KEY"))?;
+                certificate::key_matches(&leaf, label, key)
+            }
+        }
+    }
+
+    /// The name the leaf certificate is for, such as `example.com`, if it
+    /// can be read without the backend.
+    pub(crate) fn certificate_name(&self) -> Option<String> {
+        certificate::display_name(&self.pem_leaf()?)
+    }
+
+    /// The leaf certificate of a PEM identity, as DER.
+    fn pem_leaf(&self) -> Option<Vec<u8>> {
+        match *self {
+            Identity::Pkcs12 { .. } => None,
+            Identity::Pem {
+                ref certificate_chain,
+                ..
+            } => pem_blocks(certificate_chain)
+                .ok()?
+                .into_iter()
+                .find(|(label, _)| label == "CERTIFICATE")
+                .map(|(_, der)| der),
+        }
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Identity::Pkcs12 { .. } => f.pad("Identity::Pkcs12(..)"),
+            Identity::Pem { .. } => f.pad("Identity::Pem(..)"),
+        }
+    }
+}
+
+/// The certificate authorities trusted to verify servers.
+#[derive(Clone, Debug)]
+pub struct RootCertificates {
+    certificates: Vec<Vec<u8>>,
+    include_system: bool,
+}
+
+impl RootCertificates {
+    /// The backend's default roots, usually the system store.
+    pub fn system() -> RootCertificates {
+        RootCertificates {
+            certificates: Vec::new(),
+            include_system: true,
+        }
+    }
+
+    /// No roots at all, for replacing the system store with roots added
+    /// through `add_der` and `add_pem`.
+    pub fn empty() -> RootCertificates {
+        RootCertificates {
+            certificates: Vec::new(),
+            include_system: false,
+        }
+    }
+
+    /// Trust a DER-encoded certificate.
+    pub fn add_der(&mut self, der: &[u8]) {
+        self.certificates.push(der.to_vec());
+    }
+
+    /// Trust every certificate in a PEM bundle.
+    ///
+    /// Fails if the bundle contains no certificates.
+    pub fn add_pem(&mut self, pem: &[u8]) -> Result<(), io::Error> {
+        let before = self.certificates.len();
+        for (label, der) in pem_blocks(pem)? {
+            if label == "CERTIFICATE" {
+                self.certificates.push(der);
+            }
+        }
+        if self.certificates.len() == before {
+            return Err(invalid("no certificate in the PEM bundle"));
+        }
+        Ok(())
+    }
+
+    /// The added certificates, as DER.
+    pub fn certificates(&self) -> &[Vec<u8>] {
+        &self.certificates
+    }
+
+    /// Whether the backend's default roots are trusted as well.
+    pub fn includes_system(&self) -> bool {
+        self.include_system
+    }
+}
+
+impl Default for RootCertificates {
+    fn default() -> RootCertificates {
+        RootCertificates::system()
+    }
+}
+
+pub(crate) type Hook<B> = Arc<dyn Fn(&mut B) -> tls_api::Result<()> + Send + Sync>;
+type IdentityHook<B> = Arc<dyn Fn(&mut B, &Identity) -> tls_api::Result<()> + Send + Sync>;
+
+/// Connector builder operations that `tls_api::TlsConnectorBuilder` lacks,
+/// given as closures over the backend's builder `B`: presenting a client
+/// certificate, replacing the default roots and leaving out SNI, plus any
+/// configuration of your own.
+///
+/// Backends only offer these through `underlying_mut`. A connector that
+/// needs an operation it was not given fails to build, naming the missing
+/// hook.
+pub struct ClientTlsHooks<B> {
+    build: Option<Hook<B>>,
+    set_identity: Option<IdentityHook<B>>,
+    clear_roots: Option<Hook<B>>,
+    disable_sni: Option<Hook<B>>,
+}
+
+impl<B> ClientTlsHooks<B> {
+    /// No hooks at all, which is enough for the backend's defaults.
+    pub fn new() -> ClientTlsHooks<B> {
+        ClientTlsHooks {
+            build: None,
+            set_identity: None,
+            clear_roots: None,
+            disable_sni: None,
+        }
+    }
+
+    /// Customize every fresh builder, before anything else is applied to it.
+    pub fn on_build<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut B) -> tls_api::Result<()> + Send + Sync + 'static,
+    {
+        self.build = Some(Arc::new(hook));
+    }
+
+    /// Present an identity to servers that request a client certificate.
+    pub fn on_set_identity<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut B, &Identity) -> tls_api::Result<()> + Send + Sync + 'static,
+    {
+        self.set_identity = Some(Arc::new(hook));
+    }
+
+    /// Forget every trusted root, including the backend's defaults.
+    pub fn on_clear_roots<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut B) -> tls_api::Result<()> + Send + Sync + 'static,
+    {
+        self.clear_roots = Some(Arc::new(hook));
+    }
+
+    /// Leave the server name indication (SNI) extension out.
+    pub fn on_disable_sni<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut B) -> tls_api::Result<()> + Send + Sync + 'static,
+    {
+        self.disable_sni = Some(Arc::new(hook));
+    }
+}
+
+impl<B: TlsConnectorBuilder> ClientTlsHooks<B> {
+    /// Applies the `on_build` hook, `identity` and `roots` to a fresh
+    /// builder.
+    pub(crate) fn configure(
+        &self,
+        builder: &mut B,
+        identity: Option<&Identity>,
+        roots: &RootCertificates,
+    ) -> tls_api::Result<()> {
+        if let Some(ref build) = self.build {
+            build(builder)?;
+        }
+        if let Some(identity) = identity {
+            let set_identity = self
+                .set_identity
+                .as_ref()
+                .ok_or_else(|| missing("on_set_identity", "present a client certificate"))?;
+            set_identity(builder, identity)?;
+        }
+        if !roots.include_system {
+            let clear_roots = self
+                .clear_roots
+                .as_ref()
+                .ok_or_else(|| missing("on_clear_roots", "replace the default roots"))?;
+            clear_roots(builder)?;
+        }
+        for der in &roots.certificates {
+            builder.add_root_certificate(tls_api::Certificate::from_der(der.clone()))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn disable_sni(&self, builder: &mut B) -> tls_api::Result<()> {
+        let disable_sni = self
+            .disable_sni
+            .as_ref()
+            .ok_or_else(|| missing("on_disable_sni", "leave out SNI"))?;
+        disable_sni(builder)
+    }
+}
+
+pub(crate) fn missing(hook: &str, operation: &str) -> tls_api::Error {
+    tls_api::Error::new_other(&format!(
+        "the TLS backend needs an {} hook to {}",
+        hook, operation
+    ))
+}
+
+impl<B> Clone for ClientTlsHooks<B> {
+    fn clone(&self) -> ClientTlsHooks<B> {
+        ClientTlsHooks {
+            build: self.build.clone(),
+            set_identity: self.set_identity.clone(),
+            clear_roots: self.clear_roots.clone(),
+            disable_sni: self.disable_sni.clone(),
+        }
+    }
+}
+
+impl<B> Default for ClientTlsHooks<B> {
+    fn default() -> ClientTlsHooks<B> {
+        ClientTlsHooks::new()
+    }
+}
+
+impl<B> fmt::Debug for ClientTlsHooks<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClientTlsHooks")
+            .field("on_build", &self.build.is_some())
+            .field("on_set_identity", &self.set_identity.is_some())
+            .field("on_clear_roots", &self.clear_roots.is_some())
+            .field("on_disable_sni", &self.disable_sni.is_some())
+            .finish()
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Decodes the `-----BEGIN label-----` blocks of a PEM file.
+fn pem_blocks(pem: &[u8]) -> Result<Vec<(String, Vec<u8>)>, io::Error> {
+    let pem = ::std::str::from_utf8(pem).map_err(|_| invalid("PEM data is not UTF-8"))?;
+    let mut blocks = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    for line in pem.lines().map(str::trim) {
+        if let Some(label) = line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|l| l.strip_suffix("-----"))
+        {
+            current = Some((label.to_owned(), String::new()));
+        } else if let Some(label) = line
+            .strip_prefix("-----END ")
+            .and_then(|l| l.strip_suffix("-----"))
+        {
+            match current.take() {
+                Some((ref begin, ref body)) if begin == label => {
+                    let der = base64::decode(body)
+                        .map_err(|_| invalid("malformed base64 in PEM data"))?;
+                    blocks.push((label.to_owned(), der));
+                }
+                _ => return Err(invalid("unbalanced PEM block")),
+            }
+        } else if let Some((_, ref mut body)) = current {
+            // Skip RFC 1421 headers such as `Proc-Type: 4,ENCRYPTED`.
+            if !line.contains(':') {
+                body.push_str(line);
+            }
+        }
+    }
+    if current.is_some() {
+        return Err(invalid("unterminated PEM block"));
+    }
+    Ok(blocks)
+}
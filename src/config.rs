@@ -0,0 +1,421 @@
+use hyper::client::connect::HttpConnector;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tls_api::{TlsAcceptor, TlsAcceptorBuilder, TlsConnector};
+
+use client_tls::{ClientTlsHooks, Identity, RootCertificates};
+use pinning::SpkiHash;
+use server::HttpsAcceptor;
+use server_tls::{ClientAuth, ServerTlsHooks};
+use HttpsConnector;
+
+/// Settings for `HttpsConnector::from_config`, in a form that can be read
+/// from a configuration file with any serde format.
+///
+/// Every field may be left out. In TOML, for example:
+///
+/// ```toml
+/// ca_files = ["/etc/myservice/ca.pem"]
+/// system_roots = false
+/// alpn_protocols = ["h2", "http/1.1"]
+/// connect_timeout_ms = 2000
+/// proxy = "http://proxy.internal:3128"
+/// no_proxy = "localhost,10.0.0.0/8"
+///
+/// [identity]
+/// certificate = "/etc/myservice/client.pem"
+/// private_key = "/etc/myservice/client.key"
+///
+/// [pins]
+/// "api.example.com" = ["47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsClientConfig {
+    /// PEM bundles of certificate authorities to trust.
+    pub ca_files: Vec<PathBuf>,
+    /// Whether to trust the backend's default roots as well as `ca_files`.
+    /// Defaults to `true`.
+    pub system_roots: bool,
+    /// The client certificate to present to servers that ask for one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<IdentityConfig>,
+    /// The protocols to offer through ALPN, in order of preference.
+    pub alpn_protocols: Vec<String>,
+    /// Whether to check that certificates belong to the destination host.
+    /// Defaults to `true`; see `danger_disable_hostname_verification`.
+    pub verify_hostname: bool,
+    /// Whether to refuse destinations other than `https`.
+    pub force_https: bool,
+    /// The connect timeout, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_ms: Option<u64>,
+    /// The handshake timeout, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handshake_timeout_ms: Option<u64>,
+    /// The timeout for the whole connection, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// The proxy to connect through, as for `set_proxy`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Destinations to reach without the proxy, as for `set_no_proxy`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_proxy: Option<String>,
+    /// Base64 SPKI hashes to pin, by host pattern, as for `pin`.
+    pub pins: BTreeMap<String, Vec<String>>,
+    /// Whether pin mismatches are only logged.
+    pub pin_report_only: bool,
+    /// The number of threads resolving names, as for `HttpsConnector::new`.
+    /// Defaults to 4.
+    pub dns_threads: usize,
+}
+
+impl Default for TlsClientConfig {
+    fn default() -> TlsClientConfig {
+        TlsClientConfig {
+            ca_files: Vec::new(),
+            system_roots: true,
+            identity: None,
+            alpn_protocols: Vec::new(),
+            verify_hostname: true,
+            force_https: false,
+            connect_timeout_ms: None,
+            handshake_timeout_ms: None,
+            timeout_ms: None,
+            proxy: None,
+            no_proxy: None,
+            pins: BTreeMap::new(),
+            pin_report_only: false,
+            dns_threads: 4,
+        }
+    }
+}
+
+/// Where to find a certificate and its private key.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum IdentityConfig {
+    /// A PEM certificate chain, leaf first, and a PEM private key.
+    Pem {
+        certificate: PathBuf,
+        private_key: PathBuf,
+    },
+    /// A DER-encoded PKCS#12 archive and its password.
+    Pkcs12 { pkcs12: PathBuf, password: String },
+}
+
+impl IdentityConfig {
+    fn load(&self) -> io::Result<Identity> {
+        match *self {
+            IdentityConfig::Pem {
+                ref certificate,
+                ref private_key,
+            } => Identity::from_pem(&read(certificate)?, &read(private_key)?),
+            IdentityConfig::Pkcs12 {
+                ref pkcs12,
+                ref password,
+            } => Ok(Identity::from_pkcs12(&read(pkcs12)?, password)),
+        }
+    }
+}
+
+impl TlsClientConfig {
+    fn roots(&self) -> io::Result<RootCertificates> {
+        let mut roots = if self.system_roots {
+            RootCertificates::system()
+        } else {
+            RootCertificates::empty()
+        };
+        for path in &self.ca_files {
+            roots.add_pem(&read(path)?).map_err(|e| in_file(path, e))?;
+        }
+        Ok(roots)
+    }
+}
+
+impl<S: TlsConnector> HttpsConnector<HttpConnector, S> {
+    /// Build a connector from `config`, reading the files it names.
+    ///
+    /// `hooks` supplies the builder operations the settings need: an
+    /// `identity` needs `on_set_identity`, and turning `system_roots` off
+    /// needs `on_clear_roots`.
+    ///
+    /// Fails if a file cannot be read or parsed, or if a setting is invalid,
+    /// naming the file or setting at fault.
+    pub fn from_config(
+        config: &TlsClientConfig,
+        hooks: ClientTlsHooks<S::Builder>,
+    ) -> io::Result<Self> {
+        if config.dns_threads == 0 {
+            return Err(invalid("dns_threads must be at least 1".to_owned()));
+        }
+        let identity = match config.identity {
+            Some(ref identity) => Some(identity.load()?),
+            None => None,
+        };
+        let mut connector = HttpsConnector::with_identity_and_roots(
+            config.dns_threads,
+            identity,
+            config.roots()?,
+            hooks,
+        )?;
+
+        if !config.verify_hostname {
+            connector.danger_disable_hostname_verification(true);
+        }
+        if !config.alpn_protocols.is_empty() {
+            let protocols: Vec<&str> = config.alpn_protocols.iter().map(|p| &p[..]).collect();
+            connector.set_alpn_protocols(&protocols)?;
+        }
+        connector.force_https(config.force_https);
+        connector.set_connect_timeout(config.connect_timeout_ms.map(Duration::from_millis));
+        connector.set_handshake_timeout(config.handshake_timeout_ms.map(Duration::from_millis));
+        connector.set_timeout(config.timeout_ms.map(Duration::from_millis));
+
+        if let Some(ref proxy) = config.proxy {
+            let uri = proxy
+                .parse()
+                .map_err(|e| invalid(format!("invalid proxy {:?}: {}", proxy, e)))?;
+            connector.set_proxy(Some(uri))?;
+        }
+        if let Some(ref rules) = config.no_proxy {
+            connector.set_no_proxy(rules);
+        }
+        for (host, encoded) in &config.pins {
+            let hashes = encoded
+                .iter()
+                .map(|h| SpkiHash::from_base64(h))
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(|e| invalid(format!("pins for {:?}: {}", host, e)))?;
+            connector.pin(host, &hashes);
+        }
+        connector.set_pin_report_only(config.pin_report_only);
+        Ok(connector)
+    }
+}
+
+/// Settings for `TlsServerConfig::acceptor` and `HttpsAcceptor::from_config`,
+/// in a form that can be read from a configuration file with any serde
+/// format.
+///
+/// Only `identity` is required. In TOML, for example:
+///
+/// ```toml
+/// client_auth = "required"
+/// client_ca_files = ["/etc/myservice/clients-ca.pem"]
+/// alpn_protocols = ["h2", "http/1.1"]
+/// max_handshakes = 64
+/// handshake_timeout_ms = 5000
+///
+/// [identity]
+/// certificate = "/etc/myservice/server.pem"
+/// private_key = "/etc/myservice/server.key"
+///
+/// [sni."api.example.com"]
+/// certificate = "/etc/myservice/api.pem"
+/// private_key = "/etc/myservice/api.key"
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsServerConfig {
+    /// The certificate to present to clients that ask for no other.
+    pub identity: IdentityConfig,
+    /// Certificates to present instead, by the server name clients ask for
+    /// through SNI.
+    #[serde(default)]
+    pub sni: BTreeMap<String, IdentityConfig>,
+    /// Whether to ask clients for a certificate. Defaults to `none`.
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+    /// PEM bundles of the certificate authorities that issue client
+    /// certificates. Required unless `client_auth` is `none`.
+    #[serde(default)]
+    pub client_ca_files: Vec<PathBuf>,
+    /// The protocols to select from through ALPN, in order of preference.
+    #[serde(default)]
+    pub alpn_protocols: Vec<String>,
+    /// How many handshakes may be in progress at once, as for
+    /// `HttpsAcceptor::set_max_handshakes`. Defaults to 128.
+    #[serde(default = "default_max_handshakes")]
+    pub max_handshakes: usize,
+    /// How long a client may take to complete its handshake, in
+    /// milliseconds, or 0 for no limit. Defaults to 10 seconds.
+    #[serde(default = "default_handshake_timeout_ms")]
+    pub handshake_timeout_ms: u64,
+}
+
+fn default_max_handshakes() -> usize {
+    128
+}
+
+fn default_handshake_timeout_ms() -> u64 {
+    10_000
+}
+
+/// The identities and client roots named by a `TlsServerConfig`.
+struct ServerFiles {
+    identity: Identity,
+    sni: Vec<(String, Identity)>,
+    client_roots: Option<RootCertificates>,
+}
+
+impl TlsServerConfig {
+    /// A config presenting the PEM certificate chain and private key in the
+    /// given files, with every other setting at its default.
+    pub fn new(certificate: PathBuf, private_key: PathBuf) -> TlsServerConfig {
+        TlsServerConfig {
+            identity: IdentityConfig::Pem {
+                certificate,
+                private_key,
+            },
+            sni: BTreeMap::new(),
+            client_auth: ClientAuth::None,
+            client_ca_files: Vec::new(),
+            alpn_protocols: Vec::new(),
+            max_handshakes: default_max_handshakes(),
+            handshake_timeout_ms: default_handshake_timeout_ms(),
+        }
+    }
+
+    /// Read and check the files and settings, as `acceptor` does, without
+    /// building anything.
+    ///
+    /// Fails naming the file or setting at fault, for example with
+    /// "identity: key does not match certificate for example.com" when a
+    /// private key was paired with the wrong certificate. That check is skipped for PKCS#12
+    /// archives and encrypted private keys, which only the backend can
+    /// open, so a mismatch in those surfaces when building the acceptor.
+    pub fn validate(&self) -> io::Result<()> {
+        self.load().map(|_| ())
+    }
+
+    /// Build an acceptor for `accept_async` from this config, after checking
+    /// it as `validate` does.
+    ///
+    /// `hooks` supplies the builder operations the settings need: `sni`
+    /// needs `on_sni_identity`, and `client_auth` other than `none` needs
+    /// `on_client_auth`.
+    ///
+    /// The handshake limits only apply to `HttpsAcceptor::from_config`.
+    pub fn acceptor<A: TlsAcceptor>(&self, hooks: ServerTlsHooks<A::Builder>) -> io::Result<A> {
+        let files = self.load()?;
+        if !self.alpn_protocols.is_empty() && !A::Builder::supports_alpn() {
+            return Err(io::Error::other("the TLS backend does not support ALPN"));
+        }
+
+        let client_auth = files
+            .client_roots
+            .as_ref()
+            .map(|roots| (self.client_auth, roots));
+        let mut builder = hooks.builder(&files.identity, &files.sni, client_auth)?;
+        if !self.alpn_protocols.is_empty() {
+            let protocols: Vec<&[u8]> = self.alpn_protocols.iter().map(|p| p.as_bytes()).collect();
+            builder.set_alpn_protocols(&protocols)?;
+        }
+        Ok(builder.build()?)
+    }
+
+    fn load(&self) -> io::Result<ServerFiles> {
+        if self.max_handshakes == 0 {
+            return Err(invalid("max_handshakes must be at least 1".to_owned()));
+        }
+        for protocol in &self.alpn_protocols {
+            if protocol.is_empty() || protocol.len() > 255 {
+                return Err(invalid(format!("invalid ALPN protocol {:?}", protocol)));
+            }
+        }
+
+        let identity = load_identity(&self.identity, None)?;
+        let mut sni = Vec::new();
+        for (name, config) in &self.sni {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            {
+                return Err(invalid(format!("invalid SNI server name {:?}", name)));
+            }
+            sni.push((name.clone(), load_identity(config, Some(name))?));
+        }
+
+        let client_roots = match (self.client_auth, self.client_ca_files.is_empty()) {
+            (ClientAuth::None, true) => None,
+            (ClientAuth::None, false) => {
+                return Err(invalid(
+                    "client_ca_files are given but client_auth is none".to_owned(),
+                ))
+            }
+            (_, true) => {
+                return Err(invalid(
+                    "client_auth needs at least one of client_ca_files".to_owned(),
+                ))
+            }
+            (_, false) => {
+                let mut roots = RootCertificates::empty();
+                for path in &self.client_ca_files {
+                    roots.add_pem(&read(path)?).map_err(|e| in_file(path, e))?;
+                }
+                Some(roots)
+            }
+        };
+        Ok(ServerFiles {
+            identity,
+            sni,
+            client_roots,
+        })
+    }
+}
+
+/// Loads the identity presented for `server_name`, or by default, checking
+/// its key belongs with its certificate.
+fn load_identity(config: &IdentityConfig, server_name: Option<&str>) -> io::Result<Identity> {
+    let setting = match server_name {
+        Some(name) => format!("sni.{:?}", name),
+        None => "identity".to_owned(),
+    };
+    let identity = config
+        .load()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", setting, e)))?;
+    if identity.key_matches_certificate() == Some(false) {
+        return Err(invalid(match identity.certificate_name() {
+            Some(name) => format!("{}: key does not match certificate for {}", setting, name),
+            None => format!("{}: key does not match certificate", setting),
+        }));
+    }
+    Ok(identity)
+}
+
+impl<A: TlsAcceptor> HttpsAcceptor<A> {
+    /// Build an acceptor from `config` with `hooks`, reading the files it
+    /// names and applying its handshake limits.
+    ///
+    /// Fails as `TlsServerConfig::acceptor` does.
+    pub fn from_config(
+        config: &TlsServerConfig,
+        hooks: ServerTlsHooks<A::Builder>,
+    ) -> io::Result<Self> {
+        let mut acceptor = HttpsAcceptor::from(config.acceptor::<A>(hooks)?);
+        acceptor.set_max_handshakes(config.max_handshakes);
+        acceptor.set_handshake_timeout(match config.handshake_timeout_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        });
+        Ok(acceptor)
+    }
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path).map_err(|e| in_file(path, e))
+}
+
+fn in_file(path: &Path, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
@@ -0,0 +1,320 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use tls_api;
+
+/// A common reason for a TLS handshake to fail.
+///
+/// Backends describe failures in their own words; these are recognised from
+/// the messages of OpenSSL, LibreSSL and rustls, including any reported
+/// through `report_verify_error`, from SChannel's error codes, and from the
+/// I/O error underneath.
+/// The `Display` output of each is stable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HandshakeReason {
+    /// The peer's certificate, or one in its chain, has expired.
+    CertificateExpired,
+    /// The peer's certificate, or one in its chain, is not valid yet.
+    CertificateNotYetValid,
+    /// The peer's certificate does not chain to a trusted root.
+    UnknownIssuer,
+    /// The peer's certificate is not valid for the name connected to.
+    HostnameMismatch,
+    /// The two sides have no TLS version in common.
+    ProtocolVersion,
+    /// The two sides have no cipher suite in common.
+    NoSharedCipher,
+    /// The peer closed or reset the connection before the handshake
+    /// completed.
+    PeerReset,
+}
+
+impl HandshakeReason {
+    /// Whether the peer's certificate was rejected for this reason.
+    pub fn is_certificate(self) -> bool {
+        match self {
+            HandshakeReason::CertificateExpired
+            | HandshakeReason::CertificateNotYetValid
+            | HandshakeReason::UnknownIssuer
+            | HandshakeReason::HostnameMismatch => true,
+            HandshakeReason::ProtocolVersion
+            | HandshakeReason::NoSharedCipher
+            | HandshakeReason::PeerReset => false,
+        }
+    }
+
+    fn from_message(msg: &str) -> Option<HandshakeReason> {
+        MARKERS
+            .iter()
+            .find(|&&(_, markers)| markers.iter().any(|m| msg.contains(m)))
+            .map(|&(reason, _)| reason)
+    }
+
+    fn from_io(err: &io::Error) -> Option<HandshakeReason> {
+        match err.kind() {
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => Some(HandshakeReason::PeerReset),
+            _ => None,
+        }
+    }
+
+    fn from_os_error(code: i32) -> Option<HandshakeReason> {
+        SCHANNEL_CODES
+            .iter()
+            .find(|&&(_, codes)| codes.contains(&(code as u32)))
+            .map(|&(reason, _)| reason)
+    }
+}
+
+impl fmt::Display for HandshakeReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            HandshakeReason::CertificateExpired => "certificate has expired",
+            HandshakeReason::CertificateNotYetValid => "certificate is not yet valid",
+            HandshakeReason::UnknownIssuer => "certificate issuer is unknown",
+            HandshakeReason::HostnameMismatch => "certificate does not match the host name",
+            HandshakeReason::ProtocolVersion => "no TLS version in common",
+            HandshakeReason::NoSharedCipher => "no cipher suite in common",
+            HandshakeReason::PeerReset => "peer reset the connection during the handshake",
+        })
+    }
+}
+
+/// Lower-cased fragments of backend messages, checked in order: OpenSSL's
+/// and LibreSSL's, and those of rustls before and after 0.20 and since 0.23.
+const MARKERS: &[(HandshakeReason, &[&str])] = &[
+    (
+        HandshakeReason::CertificateExpired,
+        &[
+            "certificate has expired",
+            "certexpired",
+            "invalid peer certificate: expired",
+            "certificate expired",
+        ],
+    ),
+    (
+        HandshakeReason::CertificateNotYetValid,
+        &[
+            "certificate is not yet valid",
+            "certnotvalidyet",
+            "invalid peer certificate: notvalidyet",
+            "certificate not valid yet",
+        ],
+    ),
+    (
+        HandshakeReason::HostnameMismatch,
+        &[
+            "hostname mismatch",
+            "certnotvalidforname",
+            "invalid peer certificate: notvalidforname",
+            "certificate not valid for name",
+        ],
+    ),
+    (
+        HandshakeReason::UnknownIssuer,
+        &[
+            "unable to get local issuer certificate",
+            "unable to get issuer certificate",
+            "self-signed certificate",
+            "self signed certificate",
+            "unknownissuer",
+        ],
+    ),
+    (
+        HandshakeReason::ProtocolVersion,
+        &[
+            "unsupported protocol",
+            "wrong version number",
+            "alert protocol version",
+            "no protocols available",
+            "alert: protocolversion",
+        ],
+    ),
+    (
+        HandshakeReason::NoSharedCipher,
+        &[
+            "no shared cipher",
+            "no cipher suites in common",
+            "no ciphersuites in common",
+            "nociphersuitesincommon",
+        ],
+    ),
+    (HandshakeReason::PeerReset, &["unexpected eof"]),
+];
+
+/// The HRESULTs SChannel fails handshakes with, which reach us as raw OS
+/// errors, checked in order.
+const SCHANNEL_CODES: &[(HandshakeReason, &[u32])] = &[
+    // CERT_E_EXPIRED, SEC_E_CERT_EXPIRED. SChannel does not tell expired
+    // certificates from those not valid yet.
+    (
+        HandshakeReason::CertificateExpired,
+        &[0x800B_0101, 0x8009_0328],
+    ),
+    // CERT_E_CN_NO_MATCH, SEC_E_WRONG_PRINCIPAL
+    (
+        HandshakeReason::HostnameMismatch,
+        &[0x800B_010F, 0x8009_0322],
+    ),
+    // CERT_E_UNTRUSTEDROOT, CERT_E_CHAINING, SEC_E_UNTRUSTED_ROOT
+    (
+        HandshakeReason::UnknownIssuer,
+        &[0x800B_0109, 0x800B_010A, 0x8009_0325],
+    ),
+    // SEC_E_UNSUPPORTED_FUNCTION
+    (HandshakeReason::ProtocolVersion, &[0x8009_0302]),
+    // SEC_E_ALGORITHM_MISMATCH
+    (HandshakeReason::NoSharedCipher, &[0x8009_0331]),
+];
+
+/// The raw OS error behind `err`: that of an `io::Error`, or the one its
+/// message ends in, as when a backend wraps an `io::Error` without exposing
+/// it as a source.
+pub(crate) fn os_error(err: &(dyn StdError + 'static)) -> Option<i32> {
+    if let Some(code) = err
+        .downcast_ref::<io::Error>()
+        .and_then(io::Error::raw_os_error)
+    {
+        return Some(code);
+    }
+    let msg = err.to_string();
+    let start = msg.rfind("(os error ")? + "(os error ".len();
+    let end = start + msg[start..].find(')')?;
+    msg[start..end].parse().ok()
+}
+
+/// A TLS handshake that failed.
+///
+/// Carried by `Error::Handshake` and `Error::Verification`. Its `Display`
+/// output names the reason when it is a common one, and the host and port
+/// attempted when it came from `HttpsConnector`; the backend's own error is
+/// its cause.
+#[derive(Debug)]
+pub struct HandshakeFailure {
+    cause: tls_api::Error,
+    verify_error: Option<String>,
+    reason: Option<HandshakeReason>,
+    target: Option<(String, u16)>,
+}
+
+impl HandshakeFailure {
+    pub(crate) fn new(cause: tls_api::Error, verify_error: Option<String>) -> HandshakeFailure {
+        let cause = tls_api::Error::new(Exposed(cause.into_inner()));
+        let mut failure = HandshakeFailure {
+            cause,
+            verify_error,
+            reason: None,
+            target: None,
+        };
+        failure.reason = failure.diagnose();
+        failure
+    }
+
+    /// Records the host and port whose handshake this was.
+    pub(crate) fn set_target(&mut self, host: &str, port: u16) {
+        self.target = Some((host.to_owned(), port));
+    }
+
+    /// Why the handshake failed, if the reason is a common one.
+    pub fn reason(&self) -> Option<HandshakeReason> {
+        self.reason
+    }
+
+    /// The host whose handshake failed. This is the name its certificate was
+    /// checked against, so it reflects `set_tls_name`.
+    pub fn host(&self) -> Option<&str> {
+        self.target.as_ref().map(|t| &t.0[..])
+    }
+
+    /// The port of the host whose handshake failed.
+    pub fn port(&self) -> Option<u16> {
+        self.target.as_ref().map(|t| t.1)
+    }
+
+    /// The error as the TLS backend reported it.
+    pub fn tls_error(&self) -> &tls_api::Error {
+        &self.cause
+    }
+
+    /// Why the backend rejected the peer's certificate, if it reported that
+    /// through `report_verify_error`.
+    pub fn verify_error(&self) -> Option<&str> {
+        self.verify_error.as_ref().map(|e| &e[..])
+    }
+
+    /// The first `io::Error` among the causes.
+    pub(crate) fn io_error(&self) -> Option<&io::Error> {
+        causes(&self.cause).find_map(|e| e.downcast_ref::<io::Error>())
+    }
+
+    fn diagnose(&self) -> Option<HandshakeReason> {
+        let reported = self.verify_error.iter().map(|e| e.to_ascii_lowercase());
+        reported
+            .chain(causes(&self.cause).map(|e| e.to_string().to_ascii_lowercase()))
+            .find_map(|msg| HandshakeReason::from_message(&msg))
+            .or_else(|| {
+                causes(&self.cause)
+                    .filter_map(os_error)
+                    .find_map(HandshakeReason::from_os_error)
+            })
+            .or_else(|| self.io_error().and_then(HandshakeReason::from_io))
+    }
+}
+
+impl fmt::Display for HandshakeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.target {
+            Some((ref host, port)) => write!(f, "TLS handshake with {}:{} failed: ", host, port)?,
+            None => f.write_str("TLS handshake failed: ")?,
+        }
+        match (self.reason, &self.verify_error) {
+            (Some(reason), _) => write!(f, "{}", reason),
+            (None, Some(verify_error)) => write!(f, "{}: {}", self.cause, verify_error),
+            (None, None) => write!(f, "{}", self.cause),
+        }
+    }
+}
+
+impl StdError for HandshakeFailure {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+/// `err` followed by its chain of sources.
+pub(crate) fn causes<'a>(
+    err: &'a (dyn StdError + 'static),
+) -> impl Iterator<Item = &'a (dyn StdError + 'static)> {
+    let mut next = Some(err);
+    ::std::iter::from_fn(move || {
+        let err = next?;
+        next = err.source();
+        Some(err)
+    })
+}
+
+/// The error inside a `tls_api::Error`, made its source.
+///
+/// `tls_api::Error::source` skips the error it wraps, so an `io::Error`
+/// reported directly by the backend could not otherwise be found.
+struct Exposed(Box<dyn StdError + Send + Sync>);
+
+impl fmt::Debug for Exposed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Exposed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for Exposed {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.0)
+    }
+}
@@ -0,0 +1,268 @@
+use failure::{Compat, Fail};
+use std::io;
+use tls_api;
+
+use diagnostic::{self, HandshakeFailure};
+use fallback::AttemptsError;
+use pinning::PinningError;
+use report::Report;
+use timeout::TimeoutError;
+use ForceHttpsError;
+
+/// Why establishing a connection failed.
+///
+/// hyper needs `io::Error`s from connectors, so `HttpsConnector` converts
+/// these with `From`, keeping the whole error inside. `Error::from_io_ref`
+/// or `From<io::Error>` gets it back out:
+///
+/// ```ignore
+/// match err.into_cause().and_then(|c| c.downcast::<io::Error>().ok()) {
+///     Some(io) => match Error::from(*io) {
+///         Error::Verification(_) | Error::Pinning(_) => { /* don't retry */ }
+///         _ => { /* maybe retry */ }
+///     },
+///     None => {}
+/// }
+/// ```
+///
+/// The causes of each variant are available through `Fail::cause`.
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Looking up the destination's addresses failed.
+    ///
+    /// Only lookups this crate makes itself end up here: those of
+    /// `set_address_fallback`, `HappyEyeballsConnector` and `socks5://`
+    /// proxies with `SocksConnector`. hyper's `HttpConnector` does not tell
+    /// its lookup failures apart from the connection's, so on the default
+    /// path they arrive as `Connect`.
+    #[fail(display = "DNS resolution failed: {}", _0)]
+    Dns(#[cause] io::Error),
+    /// The underlying transport could not connect to the destination.
+    #[fail(display = "connecting failed: {}", _0)]
+    Connect(#[cause] io::Error),
+    /// The proxy could not be reached, or it refused or broke the tunnel.
+    #[fail(display = "proxy failed: {}", _0)]
+    Proxy(#[cause] io::Error),
+    /// The TLS handshake failed, other than by rejecting a certificate.
+    #[fail(display = "{}", _0)]
+    Handshake(#[cause] HandshakeFailure),
+    /// The TLS backend rejected the peer's certificate, because its chain or
+    /// name did not verify.
+    #[fail(display = "{}", _0)]
+    Verification(#[cause] HandshakeFailure),
+    /// The peer presented none of the pinned keys.
+    #[fail(display = "{}", _0)]
+    Pinning(#[cause] PinningError),
+    /// A timeout expired.
+    #[fail(display = "{}", _0)]
+    Timeout(#[cause] TimeoutError),
+    /// The connector's settings forbid the connection.
+    #[fail(display = "{}", _0)]
+    Policy(#[cause] ForceHttpsError),
+    /// Every address the connector fell through to failed.
+    #[fail(display = "{}", _0)]
+    Attempts(#[cause] AttemptsError),
+    /// Anything else, such as a failing timer.
+    #[fail(display = "{}", _0)]
+    Io(#[cause] io::Error),
+}
+
+impl Error {
+    /// The `Error` carried by an `io::Error` from this crate, if any.
+    pub fn from_io_ref(err: &io::Error) -> Option<&Error> {
+        err.get_ref()?
+            .downcast_ref::<Compat<Error>>()
+            .map(Compat::get_ref)
+    }
+
+    /// Wraps an error from the underlying transport as `variant`, unless it
+    /// already carries an `Error`, as one from `SocksConnector` does.
+    pub(crate) fn wrap(err: io::Error, variant: fn(io::Error) -> Error) -> io::Error {
+        if Error::from_io_ref(&err).is_some() {
+            err
+        } else {
+            variant(err).into()
+        }
+    }
+
+    /// Classifies a handshake failure reported by the TLS backend, along
+    /// with what it reported during the handshake.
+    pub(crate) fn from_tls(err: tls_api::Error, report: Report) -> Error {
+        let failure = HandshakeFailure::new(err, report.verify_error);
+        if is_verification_failure(&failure) {
+            Error::Verification(failure)
+        } else {
+            Error::Handshake(failure)
+        }
+    }
+
+    /// Records on a handshake failure the host and port it was with.
+    pub(crate) fn attempted(mut self, host: &str, port: u16) -> Error {
+        match self {
+            Error::Handshake(ref mut f) | Error::Verification(ref mut f) => {
+                f.set_target(host, port)
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// The diagnosis of a failed TLS handshake.
+    pub fn handshake_failure(&self) -> Option<&HandshakeFailure> {
+        match *self {
+            Error::Handshake(ref f) | Error::Verification(ref f) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// Whether trying the same connection again might succeed.
+    ///
+    /// Timeouts and transport failures such as a refused connection or one
+    /// reset mid-handshake are retryable. Rejected certificates, TLS
+    /// protocol errors, refusals by a proxy, names that do not resolve and
+    /// connections forbidden by policy are not. The system resolver reports
+    /// a temporary lookup failure just like a name that does not exist, so
+    /// failed lookups only count as retryable if their error kind is one of
+    /// a transport failure. After falling through several addresses, the
+    /// error is retryable if any attempt's was.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Dns(ref e) | Error::Connect(ref e) | Error::Proxy(ref e) | Error::Io(ref e) => {
+                is_transient(e.kind())
+            }
+            Error::Handshake(ref f) => f.io_error().is_some_and(|e| is_transient(e.kind())),
+            Error::Timeout(_) => true,
+            Error::Verification(_) | Error::Pinning(_) | Error::Policy(_) => false,
+            Error::Attempts(ref e) => e
+                .failures()
+                .iter()
+                .any(|(_, e)| classify(e, Error::is_retryable)),
+        }
+    }
+
+    /// Whether the peer's certificate was rejected, by the TLS backend or by
+    /// key pinning. After falling through several addresses, whether every
+    /// attempt failed this way.
+    pub fn is_certificate_error(&self) -> bool {
+        match *self {
+            Error::Verification(_) | Error::Pinning(_) => true,
+            Error::Attempts(ref e) => e
+                .failures()
+                .iter()
+                .all(|(_, e)| classify(e, Error::is_certificate_error)),
+            _ => false,
+        }
+    }
+
+    /// Whether a timeout expired, either one set on `HttpsConnector` or one
+    /// reported by the operating system. After falling through several
+    /// addresses, whether every attempt timed out.
+    pub fn is_timeout(&self) -> bool {
+        match *self {
+            Error::Timeout(_) => true,
+            Error::Attempts(ref e) => e
+                .failures()
+                .iter()
+                .all(|(_, e)| classify(e, Error::is_timeout)),
+            _ => self.io_kind() == io::ErrorKind::TimedOut,
+        }
+    }
+
+    /// The kind of the `io::Error` this converts to.
+    fn io_kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::Dns(ref e) | Error::Connect(ref e) | Error::Proxy(ref e) | Error::Io(ref e) => {
+                e.kind()
+            }
+            Error::Handshake(ref f) => f.io_error().map_or(io::ErrorKind::Other, io::Error::kind),
+            Error::Verification(_) | Error::Pinning(_) => io::ErrorKind::Other,
+            Error::Timeout(_) => io::ErrorKind::TimedOut,
+            Error::Policy(_) => io::ErrorKind::InvalidInput,
+            Error::Attempts(ref e) => e
+                .failures()
+                .last()
+                .map_or(io::ErrorKind::Other, |(_, e)| e.kind()),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        io::Error::new(err.io_kind(), err.compat())
+    }
+}
+
+/// Recovers the `Error` inside an `io::Error` from this crate, or wraps any
+/// other `io::Error` as `Error::Io`.
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        if Error::from_io_ref(&err).is_none() {
+            return Error::Io(err);
+        }
+        let inner = err.into_inner().expect("checked by from_io_ref");
+        match inner.downcast::<Compat<Error>>() {
+            Ok(compat) => compat.into_inner(),
+            Err(_) => unreachable!("checked by from_io_ref"),
+        }
+    }
+}
+
+/// Messages by which backends report a rejected certificate whatever the
+/// reason: OpenSSL and LibreSSL, rustls before and since 0.20, and
+/// Security.framework.
+const VERIFICATION_MARKERS: &[&str] = &[
+    "certificate verify failed",
+    "invalid certificate",
+    "invalid peer certificate",
+    "certificate is not trusted",
+];
+
+/// SChannel's HRESULTs for rejected certificates without a
+/// `HandshakeReason`: CERT_E_REVOKED, CERT_E_REVOCATION_FAILURE,
+/// CERT_E_WRONG_USAGE, CRYPT_E_REVOKED, TRUST_E_CERT_SIGNATURE and
+/// SEC_E_CERT_UNKNOWN.
+const VERIFICATION_CODES: &[u32] = &[
+    0x800B_010C,
+    0x800B_010E,
+    0x800B_0110,
+    0x8009_2010,
+    0x8009_6004,
+    0x8009_0327,
+];
+
+fn is_verification_failure(failure: &HandshakeFailure) -> bool {
+    if failure.verify_error().is_some() || failure.reason().is_some_and(|r| r.is_certificate()) {
+        return true;
+    }
+    diagnostic::causes(failure.tls_error()).any(|e| {
+        let msg = e.to_string().to_ascii_lowercase();
+        VERIFICATION_MARKERS.iter().any(|m| msg.contains(m))
+            || diagnostic::os_error(e).is_some_and(|c| VERIFICATION_CODES.contains(&(c as u32)))
+    })
+}
+
+/// Failures of the transport itself, which may not recur on a new
+/// connection.
+fn is_transient(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Applies `test` to the `Error` inside `err`, or to one of the same kind if
+/// `err` came from elsewhere.
+fn classify(err: &io::Error, test: fn(&Error) -> bool) -> bool {
+    match Error::from_io_ref(err) {
+        Some(err) => test(err),
+        None => test(&Error::Io(err.kind().into())),
+    }
+}
@@ -0,0 +1,124 @@
+use futures::future::{self, Loop};
+use futures::Future;
+use hyper::client::connect::dns::{Name, Resolve};
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use Error;
+
+type BoxedIo<T> = Box<dyn Future<Item = T, Error = io::Error> + Send>;
+
+type ResolveFn = Arc<dyn Fn(Name) -> BoxedIo<Vec<IpAddr>> + Send + Sync>;
+
+/// How `HttpsConnector` finds the addresses to fall through.
+#[derive(Clone)]
+pub(crate) struct Fallback {
+    resolve: ResolveFn,
+    max_attempts: usize,
+}
+
+impl Fallback {
+    pub fn new<R>(resolver: R, max_attempts: usize) -> Fallback
+    where
+        R: Resolve + Send + Sync + 'static,
+        R::Future: Send + 'static,
+    {
+        Fallback {
+            resolve: Arc::new(move |name| Box::new(resolver.resolve(name).map(|a| a.collect()))),
+            max_attempts,
+        }
+    }
+
+    /// The addresses of `host` to try, in order.
+    pub fn addresses(&self, host: &str) -> BoxedIo<Vec<IpAddr>> {
+        let max_attempts = self.max_attempts;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Box::new(future::ok(vec![ip]));
+        }
+        match host.parse::<Name>() {
+            Ok(name) => Box::new(
+                (self.resolve)(name)
+                    .map(move |mut addrs| {
+                        addrs.truncate(max_attempts);
+                        addrs
+                    })
+                    .map_err(|e| Error::Dns(e).into()),
+            ),
+            Err(_) => Box::new(future::err(
+                Error::Dns(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid destination host name",
+                ))
+                .into(),
+            )),
+        }
+    }
+}
+
+impl fmt::Debug for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Fallback")
+            .field("max_attempts", &self.max_attempts)
+            .finish()
+    }
+}
+
+/// Runs `attempt` against each of `addrs` in turn until one succeeds.
+pub(crate) fn first_success<T, F>(addrs: Vec<IpAddr>, mut attempt: F) -> BoxedIo<T>
+where
+    T: Send + 'static,
+    F: FnMut(IpAddr) -> BoxedIo<T> + Send + 'static,
+{
+    if addrs.is_empty() {
+        let err = io::Error::new(io::ErrorKind::NotFound, "no addresses found");
+        return Box::new(future::err(Error::Dns(err).into()));
+    }
+    let state = (addrs.into_iter(), Vec::new());
+    Box::new(future::loop_fn(state, move |(mut addrs, mut failures)| {
+        let addr = addrs.next().expect("loop ends with the last address");
+        attempt(addr).then(move |result| match result {
+            Ok(item) => Ok(Loop::Break(item)),
+            Err(e) => {
+                debug!("connection attempt to {} failed: {}", addr, e);
+                failures.push((addr, e));
+                if addrs.len() > 0 {
+                    return Ok(Loop::Continue((addrs, failures)));
+                }
+                Err(Error::Attempts(AttemptsError { failures }).into())
+            }
+        })
+    }))
+}
+
+/// The error returned by `HttpsConnector` when every address it fell
+/// through to failed.
+///
+/// It reaches callers as `Error::Attempts`.
+#[derive(Debug)]
+pub struct AttemptsError {
+    failures: Vec<(IpAddr, io::Error)>,
+}
+
+impl AttemptsError {
+    /// Each address tried and how connecting to it failed, in order.
+    pub fn failures(&self) -> &[(IpAddr, io::Error)] {
+        &self.failures
+    }
+}
+
+impl fmt::Display for AttemptsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "all {} addresses failed", self.failures.len())?;
+        for (i, &(addr, ref e)) in self.failures.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { "; " };
+            write!(f, "{}{}: {}", sep, addr, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for AttemptsError {}
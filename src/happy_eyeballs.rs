@@ -0,0 +1,202 @@
+use futures::{future, Async, Future, Poll};
+use hyper::client::connect::dns::{GaiResolver, Name, Resolve};
+use hyper::client::connect::{Connect, Connected, Destination};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use std::vec;
+use tokio_tcp::{ConnectFuture, TcpStream};
+use tokio_timer::Delay;
+
+use Error;
+
+/// The Connection Attempt Delay recommended by RFC 8305.
+const DEFAULT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connects over TCP to whichever address of the destination answers first,
+/// for use underneath `HttpsConnector` in place of `HttpConnector`.
+///
+/// As in RFC 8305 ("Happy Eyeballs"), the resolved addresses are tried
+/// alternating between IPv6 and IPv4, starting with the family of the first
+/// one returned. A new attempt starts whenever the previous one fails or
+/// has not connected within the attempt delay, while earlier attempts keep
+/// going. The first connection made is kept and the rest are dropped, so a
+/// black-holed route costs one delay rather than an OS connect timeout.
+///
+/// Only connections are raced, not the A and AAAA lookups RFC 8305 also
+/// asks for: hyper's `Resolve` answers with every family at once, so
+/// attempts start once the slower of the two lookups is done.
+#[derive(Clone)]
+pub struct HappyEyeballsConnector<R = GaiResolver> {
+    resolver: R,
+    attempt_delay: Duration,
+}
+
+impl HappyEyeballsConnector<GaiResolver> {
+    /// Resolve names with blocking `getaddrinfo` calls on `threads` threads,
+    /// as `HttpConnector::new` does.
+    pub fn new(threads: usize) -> Self {
+        HappyEyeballsConnector::with_resolver(GaiResolver::new(threads))
+    }
+}
+
+impl<R> HappyEyeballsConnector<R> {
+    /// Resolve names with `resolver`.
+    pub fn with_resolver(resolver: R) -> Self {
+        HappyEyeballsConnector {
+            resolver,
+            attempt_delay: DEFAULT_ATTEMPT_DELAY,
+        }
+    }
+
+    /// Set how long an attempt may run before the next one starts. The
+    /// default is 250 ms; RFC 8305 advises between 100 ms and 2 s.
+    pub fn set_attempt_delay(&mut self, delay: Duration) {
+        self.attempt_delay = delay;
+    }
+}
+
+impl<R> fmt::Debug for HappyEyeballsConnector<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HappyEyeballsConnector")
+            .field("attempt_delay", &self.attempt_delay)
+            .finish()
+    }
+}
+
+type BoxedIo<T> = Box<dyn Future<Item = T, Error = io::Error> + Send>;
+
+impl<R> Connect for HappyEyeballsConnector<R>
+where
+    R: Resolve + Send + Sync,
+    R::Future: Send + 'static,
+{
+    type Transport = TcpStream;
+    type Error = io::Error;
+    type Future = BoxedIo<(TcpStream, Connected)>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let port = dst
+            .port()
+            .unwrap_or(if dst.scheme() == "https" { 443 } else { 80 });
+        let host = dst.host().trim_start_matches('[').trim_end_matches(']');
+
+        let addrs: BoxedIo<Vec<IpAddr>> = match host.parse::<IpAddr>() {
+            Ok(ip) => Box::new(future::ok(vec![ip])),
+            Err(_) => match host.parse::<Name>() {
+                Ok(name) => Box::new(self.resolver.resolve(name).map(|addrs| addrs.collect())),
+                Err(_) => Box::new(future::err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid destination host name",
+                ))),
+            },
+        };
+        let addrs = addrs.map_err(|e| io::Error::from(Error::Dns(e)));
+
+        let attempt_delay = self.attempt_delay;
+        Box::new(addrs.and_then(move |addrs| {
+            let addrs: Vec<SocketAddr> = interleave(addrs)
+                .into_iter()
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect();
+            Race::new(addrs, attempt_delay).map(|tcp| (tcp, Connected::new()))
+        }))
+    }
+}
+
+/// Orders `addrs` alternating between address families, starting with the
+/// family of the first address.
+fn interleave(addrs: Vec<IpAddr>) -> Vec<IpAddr> {
+    let first_is_v6 = match addrs.first() {
+        Some(ip) => ip.is_ipv6(),
+        None => return addrs,
+    };
+    let len = addrs.len();
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|ip| ip.is_ipv6() == first_is_v6);
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    let mut ordered = Vec::with_capacity(len);
+    while ordered.len() < len {
+        ordered.extend(preferred.next());
+        ordered.extend(other.next());
+    }
+    ordered
+}
+
+/// Connection attempts, started an attempt delay apart, resolving to the
+/// first to connect.
+struct Race {
+    pending: vec::IntoIter<SocketAddr>,
+    attempts: Vec<ConnectFuture>,
+    next_attempt: Delay,
+    attempt_delay: Duration,
+    error: Option<io::Error>,
+}
+
+impl Race {
+    fn new(addrs: Vec<SocketAddr>, attempt_delay: Duration) -> Race {
+        Race {
+            pending: addrs.into_iter(),
+            attempts: Vec::new(),
+            next_attempt: Delay::new(Instant::now()),
+            attempt_delay,
+            error: None,
+        }
+    }
+
+    /// Starts connecting to the next address, if any are left.
+    fn start_next(&mut self) -> bool {
+        match self.pending.next() {
+            Some(addr) => {
+                debug!("connecting to {}", addr);
+                self.attempts.push(TcpStream::connect(&addr));
+                self.next_attempt.reset(Instant::now() + self.attempt_delay);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Future for Race {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<TcpStream, io::Error> {
+        loop {
+            let mut failed = false;
+            let mut i = 0;
+            while i < self.attempts.len() {
+                match self.attempts[i].poll() {
+                    Ok(Async::Ready(tcp)) => return Ok(Async::Ready(tcp)),
+                    Ok(Async::NotReady) => i += 1,
+                    Err(e) => {
+                        debug!("connection attempt failed: {}", e);
+                        drop(self.attempts.swap_remove(i));
+                        self.error = Some(e);
+                        failed = true;
+                    }
+                }
+            }
+
+            let due = failed
+                || self.attempts.is_empty()
+                || self
+                    .next_attempt
+                    .poll()
+                    .map_err(|e| io::Error::other(format!("connect timer failed: {}", e)))?
+                    .is_ready();
+            if due && self.start_next() {
+                continue;
+            }
+            if self.attempts.is_empty() {
+                return Err(self.error.take().unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")
+                }));
+            }
+            return Ok(Async::NotReady);
+        }
+    }
+}
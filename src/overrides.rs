@@ -0,0 +1,90 @@
+use hyper::client::connect::Destination;
+use hyper::Uri;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+/// Where to connect instead of a destination's own host and port, like
+/// curl's `--connect-to` and `--resolve`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Overrides {
+    connect_to: HashMap<(String, u16), (String, u16)>,
+    resolve: HashMap<(String, u16), SocketAddr>,
+    tls_names: HashMap<(String, u16), String>,
+}
+
+impl Overrides {
+    pub fn connect_to(
+        &mut self,
+        host: &str,
+        port: u16,
+        to_host: &str,
+        to_port: u16,
+    ) -> io::Result<()> {
+        parse_authority(to_host, to_port)?;
+        self.connect_to.insert(
+            (host.to_ascii_lowercase(), port),
+            (to_host.to_ascii_lowercase(), to_port),
+        );
+        Ok(())
+    }
+
+    pub fn resolve(&mut self, host: &str, port: u16, addr: SocketAddr) {
+        self.resolve.insert((host.to_ascii_lowercase(), port), addr);
+    }
+
+    pub fn set_tls_name(&mut self, host: &str, port: u16, name: &str) {
+        self.tls_names
+            .insert((host.to_ascii_lowercase(), port), name.to_owned());
+    }
+
+    /// The name to use for SNI and verification with `host:port`, if
+    /// overridden.
+    pub fn tls_name(&self, host: &str, port: u16) -> Option<&str> {
+        self.tls_names
+            .get(&(host.to_ascii_lowercase(), port))
+            .map(|name| &name[..])
+    }
+
+    /// The host and port to connect to for `host:port`, if overridden.
+    ///
+    /// `--connect-to` entries apply first, and the host they name is then
+    /// looked up among the `--resolve` entries, as curl does.
+    pub fn target(&self, host: &str, port: u16) -> Option<(String, u16)> {
+        let key = (host.to_ascii_lowercase(), port);
+        let redirected = self.connect_to.get(&key);
+        let key = redirected.unwrap_or(&key);
+        match self.resolve.get(key) {
+            Some(addr) => Some((ip_host(addr.ip()), addr.port())),
+            None => redirected.cloned(),
+        }
+    }
+}
+
+/// `ip` as the host part of a URI.
+pub(crate) fn ip_host(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{}]", ip),
+    }
+}
+
+/// `dst` with its host and port replaced by `host:port`.
+pub(crate) fn redirect(dst: &Destination, host: &str, port: u16) -> io::Result<Destination> {
+    let uri = format!("{}://{}", dst.scheme(), parse_authority(host, port)?);
+    let uri = uri
+        .parse::<Uri>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Destination::try_from_uri(uri).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn parse_authority(host: &str, port: u16) -> io::Result<String> {
+    let authority = format!("{}:{}", host, port);
+    match format!("http://{}/", authority).parse::<Uri>() {
+        Ok(ref uri) if uri.port_part().map(|p| p.as_u16()) == Some(port) => Ok(authority),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid override target {:?}", authority),
+        )),
+    }
+}
@@ -0,0 +1,186 @@
+use base64;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+use certificate::subject_public_key_info;
+use session::SessionInfo;
+use Error;
+
+/// The SHA-256 hash of a certificate's SubjectPublicKeyInfo, as used by
+/// HPKP-style `pin-sha256` pins.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpkiHash(pub [u8; 32]);
+
+impl SpkiHash {
+    /// Hash the public key of a DER-encoded certificate.
+    pub fn of_certificate(der: &[u8]) -> Result<SpkiHash, io::Error> {
+        let spki = subject_public_key_info(der).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "malformed X.509 certificate")
+        })?;
+        Ok(SpkiHash::of_public_key_info(spki))
+    }
+
+    /// Hash a DER-encoded SubjectPublicKeyInfo.
+    pub fn of_public_key_info(spki: &[u8]) -> SpkiHash {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&Sha256::digest(spki));
+        SpkiHash(hash)
+    }
+
+    /// Parse a base64 hash, as printed by `Display` and by
+    /// `openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`.
+    pub fn from_base64(encoded: &str) -> Result<SpkiHash, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid SPKI SHA-256 pin");
+        let bytes = base64::decode(encoded).map_err(|_| invalid())?;
+        if bytes.len() != 32 {
+            return Err(invalid());
+        }
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&bytes);
+        Ok(SpkiHash(hash))
+    }
+}
+
+impl fmt::Display for SpkiHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&base64::encode(&self.0))
+    }
+}
+
+impl fmt::Debug for SpkiHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SpkiHash({})", self)
+    }
+}
+
+/// The error returned by `HttpsConnector` when a pinned host presents a
+/// certificate chain without any of the pinned keys.
+///
+/// It reaches callers as `Error::Pinning`.
+#[derive(Debug)]
+pub struct PinningError {
+    host: String,
+    observed: Vec<SpkiHash>,
+    reported: bool,
+}
+
+impl PinningError {
+    /// The host that failed the check.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The hashes of the keys in the server's chain, leaf first.
+    ///
+    /// Empty if the chain could not be observed, which is the case with
+    /// TLS 1.3 unless the backend reports it (see
+    /// `SessionInfo::peer_certificates`). If the backend did not report it,
+    /// only the leaf was checked against the pins.
+    pub fn observed(&self) -> &[SpkiHash] {
+        &self.observed
+    }
+}
+
+impl fmt::Display for PinningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.observed.first() {
+            Some(leaf) if !self.reported && self.observed.len() > 1 => write!(
+                f,
+                "no pinned key in the leaf certificate of {} (leaf key {}); \
+                 the rest of the chain only counts if the backend reports it \
+                 through report_peer_certificates",
+                self.host, leaf
+            ),
+            Some(leaf) => write!(
+                f,
+                "no pinned key in the certificate chain of {} (leaf key {})",
+                self.host, leaf
+            ),
+            None => write!(
+                f,
+                "the certificate chain of {} could not be observed for pinning; \
+                 with TLS 1.3 the backend must report it through report_peer_certificates",
+                self.host
+            ),
+        }
+    }
+}
+
+impl StdError for PinningError {}
+
+/// Pinned keys by host pattern.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Pins {
+    hosts: HashMap<String, Vec<SpkiHash>>,
+    report_only: bool,
+}
+
+impl Pins {
+    pub fn insert(&mut self, pattern: &str, hashes: Vec<SpkiHash>) {
+        self.hosts.insert(pattern.to_ascii_lowercase(), hashes);
+    }
+
+    pub fn set_report_only(&mut self, report_only: bool) {
+        self.report_only = report_only;
+    }
+
+    /// The pins for `host`: an exact match, else the most specific
+    /// `*.suffix` pattern, else the `*` default.
+    fn lookup(&self, host: &str) -> Option<&[SpkiHash]> {
+        let host = host.to_ascii_lowercase();
+        if let Some(hashes) = self.hosts.get(&host) {
+            return Some(hashes);
+        }
+        let mut suffix = &host[..];
+        while let Some(dot) = suffix.find('.') {
+            suffix = &suffix[dot + 1..];
+            if let Some(hashes) = self.hosts.get(&format!("*.{}", suffix)) {
+                return Some(hashes);
+            }
+        }
+        self.hosts.get("*").map(|hashes| &hashes[..])
+    }
+
+    /// Check the server's chain against the pins for `host`.
+    ///
+    /// Only the leaf of a chain read off the wire counts: anything after it
+    /// is whatever the server chose to send, and need not be what the
+    /// backend verified the leaf through.
+    pub fn check(&self, host: &str, session: &SessionInfo) -> Result<(), io::Error> {
+        let pinned = match self.lookup(host) {
+            Some(pinned) => pinned,
+            None => return Ok(()),
+        };
+        let chain = session.peer_certificates().unwrap_or(&[]);
+        let reported = session.peer_certificates_reported();
+        let checked = if reported {
+            chain.len()
+        } else {
+            chain.len().min(1)
+        };
+        let matches = chain[..checked]
+            .iter()
+            .filter_map(|der| SpkiHash::of_certificate(der).ok())
+            .any(|hash| pinned.contains(&hash));
+        if matches {
+            return Ok(());
+        }
+
+        let err = PinningError {
+            host: host.to_owned(),
+            observed: chain
+                .iter()
+                .filter_map(|der| SpkiHash::of_certificate(der).ok())
+                .collect(),
+            reported,
+        };
+        if self.report_only {
+            warn!("{} (report only)", err);
+            return Ok(());
+        }
+        Err(Error::Pinning(err).into())
+    }
+}
@@ -0,0 +1,420 @@
+use base64;
+use futures::{Async, Future, Poll};
+use hyper::client::connect::Destination;
+use hyper::Uri;
+use std::env;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use Error;
+
+/// The largest CONNECT response head we are willing to buffer.
+const MAX_RESPONSE_HEAD: usize = 8 * 1024;
+
+/// The proxies to use, by destination scheme.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Proxies {
+    http: Option<ProxyServer>,
+    https: Option<ProxyServer>,
+    no_proxy: NoProxy,
+}
+
+impl Proxies {
+    /// Reads the proxy environment variables the way curl does.
+    ///
+    /// `http_proxy` is only honoured in lower case, since CGI servers put the
+    /// request's `Proxy` header in `HTTP_PROXY`. The lower case spelling wins
+    /// for the other variables too. Proxies with a scheme other than `http`
+    /// or `https`, such as `socks5`, are skipped with a warning.
+    pub fn from_env() -> Result<Proxies, io::Error> {
+        let all = proxy_var(&["all_proxy", "ALL_PROXY"])?;
+        let http = proxy_var(&["http_proxy"])?.or_else(|| all.clone());
+        let https = proxy_var(&["https_proxy", "HTTPS_PROXY"])?.or(all);
+        let no_proxy = var(&["no_proxy", "NO_PROXY"])
+            .map(|(_, rules)| rules)
+            .unwrap_or_default();
+        Ok(Proxies {
+            http,
+            https,
+            no_proxy: NoProxy::parse(&no_proxy),
+        })
+    }
+
+    /// Uses `server` for every scheme.
+    pub fn set_all(&mut self, server: Option<ProxyServer>) {
+        self.http = server.clone();
+        self.https = server;
+    }
+
+    pub fn set_no_proxy(&mut self, rules: &str) {
+        self.no_proxy = NoProxy::parse(rules);
+    }
+
+    /// The proxy to use for `dst`, if any.
+    pub fn for_destination(&self, dst: &Destination) -> Option<&ProxyServer> {
+        let server = match dst.scheme() {
+            "https" => self.https.as_ref(),
+            _ => self.http.as_ref(),
+        }?;
+        let port = dst.port().unwrap_or(match dst.scheme() {
+            "https" => 443,
+            _ => 80,
+        });
+        if self.no_proxy.matches(dst.host(), port) {
+            return None;
+        }
+        Some(server)
+    }
+}
+
+/// The first of `names` that is set to something, and its value.
+fn var<'a>(names: &[&'a str]) -> Option<(&'a str, String)> {
+    names
+        .iter()
+        .filter_map(|&name| env::var(name).ok().map(|value| (name, value)))
+        .find(|(_, value)| !value.trim().is_empty())
+}
+
+fn proxy_var(names: &[&str]) -> Result<Option<ProxyServer>, io::Error> {
+    let (name, value) = match var(names) {
+        Some(var) => var,
+        None => return Ok(None),
+    };
+    let value = value.trim();
+    // Like curl, treat a bare `host:port` as an HTTP proxy.
+    let uri = if value.contains("://") {
+        value.to_owned()
+    } else {
+        format!("http://{}", value)
+    };
+    let uri = uri.parse::<Uri>().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid proxy URI in {}: {}", name, e),
+        )
+    })?;
+    match uri.scheme_part().map(|s| s.as_str()) {
+        Some("http") | Some("https") => ProxyServer::from_uri(uri).map(Some),
+        scheme => {
+            warn!(
+                "ignoring {}: {:?} proxies are not supported here",
+                name,
+                scheme.unwrap_or("")
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// A proxy server and the credentials to present to it.
+#[derive(Clone)]
+pub(crate) struct ProxyServer {
+    dst: Destination,
+    authorization: Option<String>,
+}
+
+impl ProxyServer {
+    /// Parses an `http://` or `https://[user:password@]host[:port]` proxy
+    /// URI.
+    ///
+    /// The userinfo is percent-decoded and sent as Basic credentials.
+    pub fn from_uri(uri: Uri) -> Result<ProxyServer, io::Error> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let scheme = uri
+            .scheme_part()
+            .map(|s| s.as_str().to_owned())
+            .ok_or_else(|| invalid("proxy URI must be absolute".to_owned()))?;
+        if scheme != "http" && scheme != "https" {
+            return Err(invalid(format!("unsupported proxy scheme {:?}", scheme)));
+        }
+        let authority = uri
+            .authority_part()
+            .map(|a| a.as_str().to_owned())
+            .ok_or_else(|| invalid("proxy URI must be absolute".to_owned()))?;
+
+        let (credentials, host) = split_userinfo(&authority)?;
+        let authorization = credentials.map(|(user, password)| {
+            let credentials = [&user[..], b":", &password[..]].concat();
+            format!("Basic {}", base64::encode(&credentials))
+        });
+
+        // Keep the credentials out of the destination, which gets logged.
+        let uri = format!("{}://{}", scheme, host)
+            .parse()
+            .map_err(|e| invalid(format!("invalid proxy URI: {}", e)))?;
+        let dst = Destination::try_from_uri(uri)
+            .map_err(|_| invalid("proxy URI must be absolute".to_owned()))?;
+        Ok(ProxyServer { dst, authorization })
+    }
+
+    pub fn destination(&self) -> &Destination {
+        &self.dst
+    }
+
+    /// Whether the connection to the proxy itself uses TLS.
+    pub fn is_tls(&self) -> bool {
+        self.dst.scheme() == "https"
+    }
+
+    /// The `Proxy-Authorization` header value, if credentials were given.
+    pub fn authorization(&self) -> Option<&str> {
+        self.authorization.as_ref().map(|a| &a[..])
+    }
+}
+
+impl fmt::Debug for ProxyServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProxyServer")
+            .field("dst", &self.dst)
+            .field("authorization", &self.authorization.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+pub(crate) type Credentials = (Vec<u8>, Vec<u8>);
+
+/// Splits `user:password@host:port` into the percent-decoded credentials and
+/// `host:port`.
+pub(crate) fn split_userinfo(authority: &str) -> Result<(Option<Credentials>, &str), io::Error> {
+    let at = match authority.rfind('@') {
+        Some(at) => at,
+        None => return Ok((None, authority)),
+    };
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let mut parts = authority[..at].splitn(2, ':');
+    let user = percent_decode(parts.next().unwrap_or(""))
+        .ok_or_else(|| invalid("malformed proxy user name"))?;
+    let password = percent_decode(parts.next().unwrap_or(""))
+        .ok_or_else(|| invalid("malformed proxy password"))?;
+    Ok((Some((user, password)), &authority[at + 1..]))
+}
+
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hi = (bytes.next()? as char).to_digit(16)?;
+            let lo = (bytes.next()? as char).to_digit(16)?;
+            out.push((hi * 16 + lo) as u8);
+        } else {
+            out.push(b);
+        }
+    }
+    Some(out)
+}
+
+/// `NO_PROXY` rules: `*`, host names matching themselves and their
+/// subdomains, and IP addresses or CIDR blocks matching IP literals. Host
+/// names and addresses may be limited to one port, as in `example.com:8080`
+/// or `[::1]:8080`.
+#[derive(Clone, Debug, Default)]
+struct NoProxy {
+    all: bool,
+    domains: Vec<(String, Option<u16>)>,
+    networks: Vec<((IpAddr, u8), Option<u16>)>,
+}
+
+impl NoProxy {
+    /// Parses a comma or whitespace separated list.
+    fn parse(rules: &str) -> NoProxy {
+        let mut no_proxy = NoProxy::default();
+        for rule in rules.split(|c: char| c == ',' || c.is_whitespace()) {
+            if rule.is_empty() {
+                continue;
+            }
+            if rule == "*" {
+                no_proxy.all = true;
+                continue;
+            }
+            let (rule, port) = split_port(rule);
+            if let Some(network) = parse_network(rule) {
+                no_proxy.networks.push((network, port));
+            } else {
+                let domain = rule.trim_start_matches("*.").trim_start_matches('.');
+                no_proxy.domains.push((domain.to_ascii_lowercase(), port));
+            }
+        }
+        no_proxy
+    }
+
+    /// Whether `host`, as returned by `Destination::host`, bypasses the
+    /// proxy on `port`.
+    fn matches(&self, host: &str, port: u16) -> bool {
+        if self.all {
+            return true;
+        }
+        let on_port = |rule: Option<u16>| rule.is_none_or(|p| p == port);
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return self
+                .networks
+                .iter()
+                .any(|&((network, prefix), p)| on_port(p) && in_network(ip, network, prefix));
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.domains.iter().any(|(domain, p)| {
+            on_port(*p)
+                && (host == *domain
+                    || (host.ends_with(&domain[..])
+                        && host[..host.len() - domain.len()].ends_with('.')))
+        })
+    }
+}
+
+/// Splits a trailing `:port` off a rule. Bare IPv6 addresses have colons of
+/// their own, so they only take a port in brackets, as in `[::1]:8080`.
+fn split_port(rule: &str) -> (&str, Option<u16>) {
+    let colon = match rule.rfind(':') {
+        Some(colon) => colon,
+        None => return (rule, None),
+    };
+    let bracketed = rule.starts_with('[') && rule[..colon].ends_with(']');
+    if !bracketed && rule[..colon].contains(':') {
+        return (rule, None);
+    }
+    match rule[colon + 1..].parse() {
+        Ok(port) => (&rule[..colon], Some(port)),
+        Err(_) => (rule, None),
+    }
+}
+
+fn parse_network(rule: &str) -> Option<(IpAddr, u8)> {
+    let rule = rule.trim_start_matches('[');
+    let (addr, prefix) = match rule.find('/') {
+        Some(slash) => (&rule[..slash], Some(&rule[slash + 1..])),
+        None => (rule, None),
+    };
+    let addr: IpAddr = addr.trim_end_matches(']').parse().ok()?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.parse().ok().filter(|&p| p <= max)?,
+        None => max,
+    };
+    Some((addr, prefix))
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => (
+            u128::from(u32::from(ip)),
+            u128::from(u32::from(network)),
+            32,
+        ),
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - u32::from(prefix);
+    ip >> shift == network >> shift
+}
+
+/// Asks an HTTP proxy to open a tunnel to `host:port` over `stream`,
+/// presenting `authorization` as `Proxy-Authorization` if given.
+///
+/// `host` is as returned by `Destination::host`, so IPv6 literals are
+/// already bracketed.
+pub(crate) fn tunnel<T>(
+    stream: T,
+    host: &str,
+    port: u16,
+    authorization: Option<&str>,
+) -> Tunnel<T> {
+    let mut request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    );
+    if let Some(authorization) = authorization {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    Tunnel {
+        stream: Some(stream),
+        request: request.into_bytes(),
+        written: 0,
+        response: Vec::new(),
+    }
+}
+
+/// A CONNECT request in progress, resolving to the tunnelled stream.
+pub(crate) struct Tunnel<T> {
+    stream: Option<T>,
+    request: Vec<u8>,
+    written: usize,
+    response: Vec<u8>,
+}
+
+impl<T: AsyncRead + AsyncWrite> Future for Tunnel<T> {
+    type Item = T;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<T, io::Error> {
+        self.poll_tunnel().map_err(|e| Error::Proxy(e).into())
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite> Tunnel<T> {
+    fn poll_tunnel(&mut self) -> Poll<T, io::Error> {
+        let stream = self.stream.as_mut().expect("cannot poll Tunnel twice");
+        while self.written < self.request.len() {
+            let n = try_ready!(stream.poll_write(&self.request[self.written..]));
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.written += n;
+        }
+        try_ready!(stream.poll_flush());
+
+        // Read a byte at a time so nothing after the response head, which
+        // belongs to the tunnelled connection, is consumed.
+        while !self.response.ends_with(b"\r\n\r\n") {
+            if self.response.len() >= MAX_RESPONSE_HEAD {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "proxy response head too large",
+                ));
+            }
+            let mut byte = [0];
+            if try_ready!(stream.poll_read(&mut byte)) == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "proxy closed the connection before responding to CONNECT",
+                ));
+            }
+            self.response.push(byte[0]);
+        }
+
+        check_status(&self.response)?;
+        Ok(Async::Ready(self.stream.take().unwrap()))
+    }
+}
+
+/// Accepts any 2xx status line.
+fn check_status(head: &[u8]) -> Result<(), io::Error> {
+    let line = head.split(|&b| b == b'\r').next().unwrap_or(&[]);
+    let line = String::from_utf8_lossy(line);
+    let mut parts = line.splitn(3, ' ');
+    let version = parts.next().unwrap_or("");
+    let status = parts.next().and_then(|s| s.parse::<u16>().ok());
+    match status {
+        Some(status) if version.starts_with("HTTP/1.") => {
+            if (200..300).contains(&status) {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!(
+                    "proxy refused to tunnel: {}",
+                    line.trim()
+                )))
+            }
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("malformed proxy response: {:?}", line),
+        )),
+    }
+}
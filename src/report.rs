@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::mem;
+
+/// What the TLS backend reported while one handshake was being driven.
+#[derive(Debug, Default)]
+pub(crate) struct Report {
+    pub peer_certificates: Option<Vec<Vec<u8>>>,
+    pub verify_error: Option<String>,
+}
+
+thread_local! {
+    /// The report of the handshake being driven on this thread, if any.
+    static CURRENT: RefCell<Option<Report>> = const { RefCell::new(None) };
+}
+
+/// Runs one step of a handshake, adding whatever the backend reports during
+/// it to `report`.
+pub(crate) fn collect<T, F: FnOnce() -> T>(report: &mut Report, step: F) -> T {
+    /// Puts the report back where it came from, even if `step` panics.
+    struct Restore<'a> {
+        report: &'a mut Report,
+        outer: Option<Report>,
+    }
+
+    impl<'a> Drop for Restore<'a> {
+        fn drop(&mut self) {
+            let current = CURRENT.with(|c| c.replace(self.outer.take()));
+            *self.report = current.unwrap_or_default();
+        }
+    }
+
+    let outer = CURRENT.with(|c| c.replace(Some(mem::take(report))));
+    let _restore = Restore { report, outer };
+    step()
+}
+
+/// Report the peer's certificate chain, as DER with the leaf first, from
+/// inside the TLS backend.
+///
+/// tls-api cannot ask a backend for the peer's certificates, and with TLS 1.3
+/// they cannot be read off the wire either, so call this from the backend's
+/// certificate verification callback, with the chain it verified. It applies
+/// to the handshake this crate is driving on the current thread, which is
+/// where backends run such callbacks, and does nothing elsewhere. A reported
+/// chain takes the place of the one read from the handshake in
+/// `SessionInfo::peer_certificates`, and is the only one whose certificates
+/// past the leaf count for `HttpsConnector::pin`.
+///
+/// With OpenSSL, for example:
+///
+/// ```ignore
+/// let connector = HttpsConnector::with_tls_builder(http, |builder| {
+///     builder.underlying_mut().set_verify_callback(SslVerifyMode::PEER, |ok, ctx| {
+///         if !ok {
+///             hyper_tls_api::report_verify_error(ctx.error().error_string().to_owned());
+///         } else if ctx.error_depth() == 0 {
+///             if let Some(chain) = ctx.chain() {
+///                 let chain = chain.iter().filter_map(|c| c.to_der().ok()).collect();
+///                 hyper_tls_api::report_peer_certificates(chain);
+///             }
+///         }
+///         ok
+///     });
+///     Ok(())
+/// })?;
+/// ```
+pub fn report_peer_certificates(chain: Vec<Vec<u8>>) {
+    CURRENT.with(|c| {
+        if let Some(ref mut report) = *c.borrow_mut() {
+            report.peer_certificates = Some(chain);
+        }
+    });
+}
+
+/// Report why the TLS backend rejected the peer's certificate, in its own
+/// words, from inside the backend.
+///
+/// Backends often leave the reason out of the handshake error itself, as
+/// OpenSSL does with its verify result, so call this where the rejection is
+/// made, such as the verification callback shown for
+/// `report_peer_certificates`. Like that, it applies to the handshake driven
+/// on the current thread. The first message reported is kept; it makes the
+/// failure an `Error::Verification`, is diagnosed like the backend's own
+/// messages, and is available as `HandshakeFailure::verify_error`.
+pub fn report_verify_error(message: String) {
+    CURRENT.with(|c| {
+        if let Some(ref mut report) = *c.borrow_mut() {
+            report.verify_error.get_or_insert(message);
+        }
+    });
+}
@@ -0,0 +1,50 @@
+use futures::future::{self, FutureResult};
+use hyper::client::connect::dns::{Name, Resolve};
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::vec;
+
+/// A resolver answering from a fixed table of host names, for routing names
+/// to test servers without touching `/etc/hosts`.
+///
+/// Names are matched case-insensitively. Looking up a name that is not in
+/// the table fails with an `io::Error` of kind `NotFound`; IP literals never
+/// reach the resolver.
+#[derive(Clone, Debug, Default)]
+pub struct StaticResolver {
+    hosts: Arc<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl StaticResolver {
+    /// An empty table.
+    pub fn new() -> StaticResolver {
+        StaticResolver::default()
+    }
+
+    /// Resolve `host` to `addr`, in addition to any addresses it already
+    /// has. Addresses are returned in the order they were added.
+    pub fn insert(&mut self, host: &str, addr: IpAddr) -> &mut Self {
+        Arc::make_mut(&mut self.hosts)
+            .entry(host.to_ascii_lowercase())
+            .or_default()
+            .push(addr);
+        self
+    }
+}
+
+impl Resolve for StaticResolver {
+    type Addrs = vec::IntoIter<IpAddr>;
+    type Future = FutureResult<Self::Addrs, io::Error>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        match self.hosts.get(&name.as_str().to_ascii_lowercase()) {
+            Some(addrs) => future::ok(addrs.clone().into_iter()),
+            None => future::err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no static address for {}", name),
+            )),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use futures::stream::FuturesUnordered;
+use futures::{Async, Future, Poll, Stream};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tls_api::TlsAcceptor;
+use tokio_tcp::{TcpListener, TcpStream};
+use tokio_timer::Delay;
+
+use {accept_async, AcceptAsync, TlsStream};
+
+/// Accepts TLS connections for a `hyper::Server`.
+pub struct HttpsAcceptor<A> {
+    tls: Arc<A>,
+    max_handshakes: usize,
+    handshake_timeout: Option<Duration>,
+}
+
+impl<A: TlsAcceptor> HttpsAcceptor<A> {
+    /// Bind a listener to `addr` and accept TLS connections on it.
+    pub fn bind(&self, addr: &SocketAddr) -> Result<HttpsIncoming<A>, io::Error> {
+        Ok(self.incoming(TcpListener::bind(addr)?))
+    }
+
+    /// Accept TLS connections on `listener`.
+    ///
+    /// Handshakes run concurrently, and connections are yielded in the order
+    /// their handshakes complete. Failed and timed out handshakes are logged
+    /// and the connection is dropped. Accept errors are logged too; errors
+    /// other than a connection failing early, such as running out of file
+    /// descriptors, pause accepting for a second.
+    pub fn incoming(&self, listener: TcpListener) -> HttpsIncoming<A> {
+        HttpsIncoming {
+            listener,
+            tls: self.tls.clone(),
+            max_handshakes: self.max_handshakes,
+            handshake_timeout: self.handshake_timeout,
+            handshakes: FuturesUnordered::new(),
+            backoff: None,
+        }
+    }
+}
+
+impl<A> HttpsAcceptor<A> {
+    /// Set how many handshakes may be in progress at once.
+    ///
+    /// Further connections wait in the listen backlog until a handshake
+    /// finishes. Default is 128.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn set_max_handshakes(&mut self, max: usize) {
+        assert!(max > 0, "max_handshakes must be at least 1");
+        self.max_handshakes = max;
+    }
+
+    /// Set how long a client may take to complete its handshake.
+    ///
+    /// Connections still handshaking after `timeout` are dropped. `None`
+    /// lets handshakes run indefinitely. Default is 10 seconds.
+    ///
+    /// The deadline runs on the `tokio-timer` of the task polling the
+    /// incoming stream, such as a tokio runtime.
+    pub fn set_handshake_timeout(&mut self, timeout: Option<Duration>) {
+        self.handshake_timeout = timeout;
+    }
+}
+
+impl<A> From<A> for HttpsAcceptor<A> {
+    fn from(tls: A) -> HttpsAcceptor<A> {
+        HttpsAcceptor {
+            tls: Arc::new(tls),
+            max_handshakes: 128,
+            handshake_timeout: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+impl<A> Clone for HttpsAcceptor<A> {
+    fn clone(&self) -> HttpsAcceptor<A> {
+        HttpsAcceptor {
+            tls: self.tls.clone(),
+            max_handshakes: self.max_handshakes,
+            handshake_timeout: self.handshake_timeout,
+        }
+    }
+}
+
+impl<A> fmt::Debug for HttpsAcceptor<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpsAcceptor")
+            .field("max_handshakes", &self.max_handshakes)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .finish()
+    }
+}
+
+/// A stream of TLS connections accepted from a `TcpListener`.
+///
+/// Pass it to `hyper::Server::builder` to serve HTTPS.
+pub struct HttpsIncoming<A> {
+    listener: TcpListener,
+    tls: Arc<A>,
+    max_handshakes: usize,
+    handshake_timeout: Option<Duration>,
+    handshakes: FuturesUnordered<Handshake>,
+    backoff: Option<Delay>,
+}
+
+/// How long accepting pauses after an error such as running out of file
+/// descriptors, as in hyper's `AddrIncoming`.
+const ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+impl<A> HttpsIncoming<A> {
+    /// The local address of the underlying listener.
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts the next connection, riding out accept errors: connections
+    /// that failed before they were accepted are skipped, and other errors
+    /// are logged and pause accepting for `ACCEPT_BACKOFF`.
+    ///
+    /// Only fails if there is no timer to pause on.
+    fn poll_accept(&mut self) -> Poll<(TcpStream, SocketAddr), io::Error> {
+        if let Some(mut backoff) = self.backoff.take() {
+            if let Ok(Async::NotReady) = backoff.poll() {
+                self.backoff = Some(backoff);
+                return Ok(Async::NotReady);
+            }
+        }
+        loop {
+            match self.listener.poll_accept() {
+                Ok(accepted) => return Ok(accepted),
+                Err(ref e) if is_connection_error(e) => {
+                    debug!("accepted connection already failed: {}", e);
+                }
+                Err(e) => {
+                    error!("accept error: {}", e);
+                    let mut backoff = Delay::new(Instant::now() + ACCEPT_BACKOFF);
+                    match backoff.poll() {
+                        Ok(Async::NotReady) => {
+                            self.backoff = Some(backoff);
+                            return Ok(Async::NotReady);
+                        }
+                        Ok(Async::Ready(())) => {}
+                        Err(timer) => {
+                            error!("cannot pause accepting, timer failed: {}", timer);
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+impl<A: TlsAcceptor> Stream for HttpsIncoming<A> {
+    type Item = TlsStream<TcpStream>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            while self.handshakes.len() < self.max_handshakes {
+                match self.poll_accept()? {
+                    Async::Ready((tcp, remote_addr)) => self.handshakes.push(Handshake {
+                        accept: accept_async(&*self.tls, tcp),
+                        deadline: self
+                            .handshake_timeout
+                            .map(|timeout| Delay::new(Instant::now() + timeout)),
+                        remote_addr,
+                    }),
+                    Async::NotReady => break,
+                }
+            }
+
+            match self.handshakes.poll()? {
+                Async::Ready(Some(Some(stream))) => return Ok(Async::Ready(Some(stream))),
+                // A failed handshake freed up a slot, go back to the listener.
+                Async::Ready(Some(None)) => continue,
+                // The listener never runs dry, so an empty set of handshakes
+                // just means we are waiting for the next connection.
+                Async::Ready(None) | Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+impl<A> fmt::Debug for HttpsIncoming<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpsIncoming")
+            .field("listener", &self.listener)
+            .field("handshakes", &self.handshakes.len())
+            .finish()
+    }
+}
+
+/// A single server handshake, resolving to `None` if it failed or ran out of
+/// time. It never fails, so that one connection cannot end the stream.
+struct Handshake {
+    accept: AcceptAsync<TcpStream>,
+    deadline: Option<Delay>,
+    remote_addr: SocketAddr,
+}
+
+impl Future for Handshake {
+    type Item = Option<TlsStream<TcpStream>>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.accept.poll() {
+            Ok(Async::Ready(stream)) => return Ok(Async::Ready(Some(stream))),
+            Ok(Async::NotReady) => {}
+            Err(e) => {
+                debug!("TLS handshake with {} failed: {}", self.remote_addr, e);
+                return Ok(Async::Ready(None));
+            }
+        }
+
+        if let Some(ref mut deadline) = self.deadline {
+            match deadline.poll() {
+                Ok(Async::NotReady) => {}
+                Ok(Async::Ready(())) => {
+                    debug!("TLS handshake with {} timed out", self.remote_addr);
+                    return Ok(Async::Ready(None));
+                }
+                Err(e) => {
+                    warn!("handshake timer for {} failed: {}", self.remote_addr, e);
+                    return Ok(Async::Ready(None));
+                }
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+use std::sync::Arc;
+use tls_api;
+
+use client_tls::{missing, Hook, Identity, RootCertificates};
+
+/// Whether a server asks clients for a certificate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuth {
+    /// Clients are not asked for a certificate.
+    #[default]
+    None,
+    /// Clients are asked for a certificate, but may connect without one.
+    /// One that is presented must verify.
+    Optional,
+    /// Clients must present a certificate that verifies.
+    Required,
+}
+
+type Constructor<B> = Arc<dyn Fn(&Identity) -> tls_api::Result<B> + Send + Sync>;
+type SniIdentityHook<B> = Arc<dyn Fn(&mut B, &str, &Identity) -> tls_api::Result<()> + Send + Sync>;
+type ClientAuthHook<B> =
+    Arc<dyn Fn(&mut B, ClientAuth, &RootCertificates) -> tls_api::Result<()> + Send + Sync>;
+
+/// Acceptor builder operations that `tls_api::TlsAcceptorBuilder` lacks,
+/// given as closures over the backend's builder `B`, to build an acceptor
+/// from a `TlsServerConfig`: creating a builder from an identity, choosing
+/// certificates by SNI and verifying client certificates, plus any
+/// configuration of your own.
+///
+/// Backends only offer these through their own constructors and
+/// `underlying_mut`. A config that needs an operation it was not given
+/// fails to build, naming the missing hook.
+pub struct ServerTlsHooks<B> {
+    from_identity: Constructor<B>,
+    build: Option<Hook<B>>,
+    sni_identity: Option<SniIdentityHook<B>>,
+    client_auth: Option<ClientAuthHook<B>>,
+}
+
+impl<B> ServerTlsHooks<B> {
+    /// Hooks creating builders with `from_identity`, which presents the
+    /// identity it is given to clients.
+    pub fn new<F>(from_identity: F) -> ServerTlsHooks<B>
+    where
+        F: Fn(&Identity) -> tls_api::Result<B> + Send + Sync + 'static,
+    {
+        ServerTlsHooks {
+            from_identity: Arc::new(from_identity),
+            build: None,
+            sni_identity: None,
+            client_auth: None,
+        }
+    }
+
+    /// Customize every fresh builder, before anything else is applied to it.
+    pub fn on_build<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut B) -> tls_api::Result<()> + Send + Sync + 'static,
+    {
+        self.build = Some(Arc::new(hook));
+    }
+
+    /// Present an identity instead to clients that ask for a server name
+    /// through SNI. Names should be matched exactly, ignoring ASCII case.
+    pub fn on_sni_identity<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut B, &str, &Identity) -> tls_api::Result<()> + Send + Sync + 'static,
+    {
+        self.sni_identity = Some(Arc::new(hook));
+    }
+
+    /// Ask clients for a certificate as the `ClientAuth` says, trusting only
+    /// those issued by the given roots.
+    pub fn on_client_auth<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut B, ClientAuth, &RootCertificates) -> tls_api::Result<()> + Send + Sync + 'static,
+    {
+        self.client_auth = Some(Arc::new(hook));
+    }
+
+    /// A builder presenting `identity`, or the identity of `sni` clients ask
+    /// for, and verifying client certificates as `client_auth` says.
+    pub(crate) fn builder(
+        &self,
+        identity: &Identity,
+        sni: &[(String, Identity)],
+        client_auth: Option<(ClientAuth, &RootCertificates)>,
+    ) -> tls_api::Result<B> {
+        let mut builder = (self.from_identity)(identity)?;
+        if let Some(ref build) = self.build {
+            build(&mut builder)?;
+        }
+        if !sni.is_empty() {
+            let sni_identity = self
+                .sni_identity
+                .as_ref()
+                .ok_or_else(|| missing("on_sni_identity", "choose a certificate by SNI"))?;
+            for (name, identity) in sni {
+                sni_identity(&mut builder, name, identity)?;
+            }
+        }
+        if let Some((mode, roots)) = client_auth {
+            let set_client_auth = self
+                .client_auth
+                .as_ref()
+                .ok_or_else(|| missing("on_client_auth", "verify client certificates"))?;
+            set_client_auth(&mut builder, mode, roots)?;
+        }
+        Ok(builder)
+    }
+}
+
+impl<B> Clone for ServerTlsHooks<B> {
+    fn clone(&self) -> ServerTlsHooks<B> {
+        ServerTlsHooks {
+            from_identity: self.from_identity.clone(),
+            build: self.build.clone(),
+            sni_identity: self.sni_identity.clone(),
+            client_auth: self.client_auth.clone(),
+        }
+    }
+}
+
+impl<B> fmt::Debug for ServerTlsHooks<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ServerTlsHooks")
+            .field("on_build", &self.build.is_some())
+            .field("on_sni_identity", &self.sni_identity.is_some())
+            .field("on_client_auth", &self.client_auth.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,506 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use tls_api;
+
+use certificate::PeerCertificate;
+use report::Report;
+
+/// Details of an established TLS session.
+///
+/// tls-api only exposes the negotiated ALPN protocol, so the rest is read
+/// from the plaintext part of the handshake as it passes over the wire, or
+/// taken from what the backend reports. Anything that could not be
+/// determined is `None`; see `peer_certificates` for when that applies to
+/// the certificates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionInfo {
+    version: Option<TlsVersion>,
+    cipher_suite: Option<CipherSuite>,
+    alpn_protocol: Option<Vec<u8>>,
+    server_name: Option<String>,
+    peer_certificates: Option<Vec<Vec<u8>>>,
+    peer_certificates_reported: bool,
+}
+
+impl SessionInfo {
+    /// The negotiated protocol version.
+    pub fn version(&self) -> Option<TlsVersion> {
+        self.version
+    }
+
+    /// The negotiated cipher suite.
+    pub fn cipher_suite(&self) -> Option<CipherSuite> {
+        self.cipher_suite
+    }
+
+    /// The protocol selected through ALPN.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_ref().map(|p| &p[..])
+    }
+
+    /// The server name the client sent through SNI.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_ref().map(|n| &n[..])
+    }
+
+    /// The peer's certificate chain, as DER, leaf first.
+    ///
+    /// This is the chain the backend reported through
+    /// `report_peer_certificates`, usually the one it verified. Otherwise it
+    /// is the chain the peer presented, which can only be read before TLS
+    /// 1.3 encrypts the handshake. So this is `None` for TLS 1.3 sessions
+    /// unless the backend reports the chain, and whenever the peer sent no
+    /// certificate.
+    pub fn peer_certificates(&self) -> Option<&[Vec<u8>]> {
+        self.peer_certificates.as_ref().map(|c| &c[..])
+    }
+
+    /// Whether `peer_certificates` is the chain the backend reported, rather
+    /// than the one read off the wire.
+    ///
+    /// Only a reported chain says which certificates the peer was verified
+    /// through. The peer can append any certificate to the chain it sends,
+    /// and the handshake only proves that it holds the leaf's key.
+    pub fn peer_certificates_reported(&self) -> bool {
+        self.peer_certificates_reported
+    }
+}
+
+/// The TLS details of an HTTPS connection.
+///
+/// `HttpsConnector` attaches this to the connection, and hyper copies it into
+/// the extensions of every response received over it:
+///
+/// ```ignore
+/// if let Some(tls) = response.extensions().get::<TlsInfo>() {
+///     println!("{:?} {:?}", tls.version(), tls.peer_certificate());
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TlsInfo {
+    session: SessionInfo,
+    peer_certificate: Option<PeerCertificate>,
+}
+
+impl TlsInfo {
+    pub(crate) fn new(session: &SessionInfo) -> TlsInfo {
+        let peer_certificate = session
+            .peer_certificates()
+            .and_then(|chain| chain.first())
+            .and_then(|leaf| PeerCertificate::from_der(leaf).ok());
+        TlsInfo {
+            session: session.clone(),
+            peer_certificate,
+        }
+    }
+
+    /// The negotiated protocol version.
+    pub fn version(&self) -> Option<TlsVersion> {
+        self.session.version()
+    }
+
+    /// The certificate the server presented.
+    ///
+    /// The leaf of `SessionInfo::peer_certificates`, so with TLS 1.3 it is
+    /// `None` unless the backend reports the chain through
+    /// `report_peer_certificates`.
+    pub fn peer_certificate(&self) -> Option<&PeerCertificate> {
+        self.peer_certificate.as_ref()
+    }
+
+    /// Everything known about the session.
+    pub fn session_info(&self) -> &SessionInfo {
+        &self.session
+    }
+}
+
+/// A TLS protocol version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TlsVersion {
+    /// SSL 3.0
+    Ssl3,
+    /// TLS 1.0
+    Tls10,
+    /// TLS 1.1
+    Tls11,
+    /// TLS 1.2
+    Tls12,
+    /// TLS 1.3
+    Tls13,
+    /// A version this crate does not know about, by wire value.
+    Unknown(u16),
+}
+
+impl TlsVersion {
+    fn from_wire(version: u16) -> TlsVersion {
+        match version {
+            0x0300 => TlsVersion::Ssl3,
+            0x0301 => TlsVersion::Tls10,
+            0x0302 => TlsVersion::Tls11,
+            0x0303 => TlsVersion::Tls12,
+            0x0304 => TlsVersion::Tls13,
+            other => TlsVersion::Unknown(other),
+        }
+    }
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TlsVersion::Ssl3 => f.pad("SSLv3"),
+            TlsVersion::Tls10 => f.pad("TLSv1"),
+            TlsVersion::Tls11 => f.pad("TLSv1.1"),
+            TlsVersion::Tls12 => f.pad("TLSv1.2"),
+            TlsVersion::Tls13 => f.pad("TLSv1.3"),
+            TlsVersion::Unknown(v) => write!(f, "0x{:04x}", v),
+        }
+    }
+}
+
+/// A TLS cipher suite, by its IANA-assigned value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CipherSuite(pub u16);
+
+const CIPHER_SUITE_NAMES: &[(u16, &str)] = &[
+    (0x002f, "TLS_RSA_WITH_AES_128_CBC_SHA"),
+    (0x0035, "TLS_RSA_WITH_AES_256_CBC_SHA"),
+    (0x009c, "TLS_RSA_WITH_AES_128_GCM_SHA256"),
+    (0x009d, "TLS_RSA_WITH_AES_256_GCM_SHA384"),
+    (0x009e, "TLS_DHE_RSA_WITH_AES_128_GCM_SHA256"),
+    (0x009f, "TLS_DHE_RSA_WITH_AES_256_GCM_SHA384"),
+    (0x1301, "TLS_AES_128_GCM_SHA256"),
+    (0x1302, "TLS_AES_256_GCM_SHA384"),
+    (0x1303, "TLS_CHACHA20_POLY1305_SHA256"),
+    (0xc009, "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA"),
+    (0xc00a, "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA"),
+    (0xc013, "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA"),
+    (0xc014, "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA"),
+    (0xc02b, "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256"),
+    (0xc02c, "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"),
+    (0xc02f, "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"),
+    (0xc030, "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"),
+    (0xcca8, "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256"),
+    (0xcca9, "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256"),
+];
+
+impl CipherSuite {
+    /// The IANA name of the suite, if it is a commonly used one.
+    pub fn name(&self) -> Option<&'static str> {
+        CIPHER_SUITE_NAMES
+            .iter()
+            .find(|&&(id, _)| id == self.0)
+            .map(|&(_, name)| name)
+    }
+}
+
+impl fmt::Display for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.pad(name),
+            None => write!(f, "0x{:04x}", self.0),
+        }
+    }
+}
+
+/// Upper bound on how much handshake traffic is kept per direction.
+const CAPTURE_LIMIT: usize = 64 * 1024;
+
+/// Wraps a transport during the handshake and keeps a copy of the bytes
+/// passing through it.
+#[derive(Debug)]
+pub(crate) struct Recorder<S> {
+    inner: S,
+    capture: Option<Capture>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Capture {
+    inbound: Vec<u8>,
+    outbound: Vec<u8>,
+}
+
+fn record(buf: &mut Vec<u8>, bytes: &[u8]) {
+    let room = CAPTURE_LIMIT.saturating_sub(buf.len());
+    buf.extend_from_slice(&bytes[..bytes.len().min(room)]);
+}
+
+impl<S> Recorder<S> {
+    pub fn new(inner: S) -> Recorder<S> {
+        Recorder {
+            inner,
+            capture: Some(Capture::default()),
+        }
+    }
+
+    /// Stop recording and hand back what was seen so far.
+    pub fn finish(&mut self) -> Capture {
+        self.capture.take().unwrap_or_default()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+impl<S: Read> Read for Recorder<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(ref mut capture) = self.capture {
+            record(&mut capture.inbound, &buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for Recorder<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(ref mut capture) = self.capture {
+            record(&mut capture.outbound, &buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Presents a stream negotiated over a `Recorder` as a stream over the
+/// recorder's transport, keeping the recorder out of the public types.
+#[derive(Debug)]
+pub(crate) struct Unrecorded<S>(pub tls_api::TlsStream<Recorder<S>>);
+
+impl<S> Read for Unrecorded<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<S> Write for Unrecorded<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S> tls_api::TlsStreamImpl<S> for Unrecorded<S>
+where
+    S: Read + Write + fmt::Debug + Send + Sync + 'static,
+{
+    fn get_alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.0.get_alpn_protocol()
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.0.shutdown()
+    }
+
+    fn get_mut(&mut self) -> &mut S {
+        self.0.get_mut().get_mut()
+    }
+
+    fn get_ref(&self) -> &S {
+        self.0.get_ref().get_ref()
+    }
+}
+
+const CONTENT_ALERT: u8 = 21;
+const CONTENT_HANDSHAKE: u8 = 22;
+
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const HANDSHAKE_SERVER_HELLO: u8 = 2;
+const HANDSHAKE_CERTIFICATE: u8 = 11;
+
+const EXTENSION_SERVER_NAME: u16 = 0;
+const EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+
+/// Bounds-checked reads over handshake structures.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2)
+            .map(|b| u16::from(b[0]) << 8 | u16::from(b[1]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.bytes(3)
+            .map(|b| (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()? as usize;
+        self.bytes(len)
+    }
+
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.bytes(len)
+    }
+
+    fn vec24(&mut self) -> Option<&'a [u8]> {
+        let len = self.u24()?;
+        self.bytes(len)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Joins the plaintext handshake records sent in one direction. Parsing
+/// stops at the first record that is not a handshake or alert, since
+/// everything after a ChangeCipherSpec or application data record is
+/// encrypted.
+fn handshake_bytes(records: &[u8]) -> Vec<u8> {
+    let mut joined = Vec::new();
+    let mut records = Reader(records);
+    while let (Some(content_type), Some(_version), Some(fragment)) =
+        (records.u8(), records.u16(), records.vec16())
+    {
+        match content_type {
+            CONTENT_HANDSHAKE => joined.extend_from_slice(fragment),
+            CONTENT_ALERT => {}
+            _ => break,
+        }
+    }
+    joined
+}
+
+/// Splits joined handshake records into `(type, body)` messages.
+fn handshake_messages(bytes: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut messages = Vec::new();
+    let mut bytes = Reader(bytes);
+    while let (Some(msg_type), Some(body)) = (bytes.u8(), bytes.vec24()) {
+        messages.push((msg_type, body));
+    }
+    messages
+}
+
+/// Walks a TLS extension block, calling `f` with each type and body.
+fn extensions<'a, F>(block: &'a [u8], mut f: F)
+where
+    F: FnMut(u16, &'a [u8]),
+{
+    let mut block = Reader(block);
+    while let (Some(ext_type), Some(body)) = (block.u16(), block.vec16()) {
+        f(ext_type, body);
+    }
+}
+
+fn parse_client_hello(info: &mut SessionInfo, body: &[u8]) -> Option<()> {
+    let mut hello = Reader(body);
+    hello.u16()?;
+    hello.bytes(32)?;
+    hello.vec8()?;
+    hello.vec16()?;
+    hello.vec8()?;
+    extensions(hello.vec16()?, |ext_type, body| {
+        if ext_type != EXTENSION_SERVER_NAME {
+            return;
+        }
+        let mut names = Reader(body);
+        let mut names = Reader(names.vec16().unwrap_or(&[]));
+        while let (Some(name_type), Some(name)) = (names.u8(), names.vec16()) {
+            if name_type == 0 {
+                info.server_name = String::from_utf8(name.to_vec()).ok();
+            }
+        }
+    });
+    Some(())
+}
+
+fn parse_server_hello(info: &mut SessionInfo, body: &[u8]) -> Option<()> {
+    let mut hello = Reader(body);
+    let mut version = hello.u16()?;
+    hello.bytes(32)?;
+    hello.vec8()?;
+    let cipher_suite = hello.u16()?;
+    hello.u8()?;
+    if !hello.is_empty() {
+        extensions(hello.vec16()?, |ext_type, body| {
+            if ext_type == EXTENSION_SUPPORTED_VERSIONS {
+                if let Some(selected) = Reader(body).u16() {
+                    version = selected;
+                }
+            }
+        });
+    }
+    info.version = Some(TlsVersion::from_wire(version));
+    info.cipher_suite = Some(CipherSuite(cipher_suite));
+    Some(())
+}
+
+fn parse_certificate(info: &mut SessionInfo, body: &[u8]) -> Option<()> {
+    let mut list = Reader(Reader(body).vec24()?);
+    let mut chain = Vec::new();
+    while !list.is_empty() {
+        chain.push(list.vec24()?.to_vec());
+    }
+    info.peer_certificates = Some(chain);
+    Some(())
+}
+
+impl Capture {
+    /// Reads what it can from the captured handshake.
+    pub fn session_info(&self) -> SessionInfo {
+        let mut info = SessionInfo::default();
+        let inbound = handshake_bytes(&self.inbound);
+        let outbound = handshake_bytes(&self.outbound);
+
+        for &(peer, bytes) in &[(true, &inbound), (false, &outbound)] {
+            for (msg_type, body) in handshake_messages(bytes) {
+                match msg_type {
+                    HANDSHAKE_CLIENT_HELLO => {
+                        parse_client_hello(&mut info, body);
+                    }
+                    HANDSHAKE_SERVER_HELLO => {
+                        parse_server_hello(&mut info, body);
+                    }
+                    HANDSHAKE_CERTIFICATE if peer => {
+                        parse_certificate(&mut info, body);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        info
+    }
+}
+
+impl SessionInfo {
+    pub(crate) fn with_alpn_protocol(mut self, protocol: Option<Vec<u8>>) -> SessionInfo {
+        self.alpn_protocol = protocol;
+        self
+    }
+
+    /// Prefers what the backend reported over what was read off the wire.
+    pub(crate) fn with_report(mut self, report: Report) -> SessionInfo {
+        if report.peer_certificates.is_some() {
+            self.peer_certificates = report.peer_certificates;
+            self.peer_certificates_reported = true;
+        }
+        self
+    }
+}
@@ -0,0 +1,293 @@
+use futures::{future, Future};
+use hyper::client::connect::dns::{GaiResolver, Name, Resolve};
+use hyper::client::connect::{Connect, Connected, Destination, HttpConnector};
+use hyper::Uri;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use tokio_io::io::{read_exact, write_all};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use proxy::{split_userinfo, Credentials};
+use Error;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USER_PASS: u8 = 2;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// Connects through a SOCKS5 proxy, for use underneath `HttpsConnector` in
+/// place of `HttpConnector`.
+///
+/// The proxy is given as `socks5://[user:password@]host[:port]`, which
+/// resolves destination names locally with `R`, or `socks5h://...`, which
+/// leaves name resolution to the proxy. The port defaults to 1080.
+#[derive(Clone)]
+pub struct SocksConnector<T = HttpConnector, R = GaiResolver> {
+    http: T,
+    proxy: Destination,
+    credentials: Option<Credentials>,
+    /// `None` for `socks5h`, whose names the proxy resolves.
+    resolver: Option<R>,
+}
+
+impl SocksConnector<HttpConnector> {
+    /// Connect to the SOCKS5 proxy at `proxy` with a fresh `HttpConnector`.
+    pub fn new(proxy: Uri) -> Result<Self, io::Error> {
+        let mut http = HttpConnector::new(1);
+        http.enforce_http(false);
+        SocksConnector::with_connector(http, proxy)
+    }
+}
+
+impl<T> SocksConnector<T> {
+    /// Connect to the SOCKS5 proxy at `proxy` using `http`. `socks5`
+    /// destinations are resolved with blocking `getaddrinfo` calls on one
+    /// thread.
+    pub fn with_connector(http: T, proxy: Uri) -> Result<Self, io::Error> {
+        SocksConnector::build(http, proxy, || GaiResolver::new(1))
+    }
+}
+
+impl<T, R> SocksConnector<T, R> {
+    /// Connect to the SOCKS5 proxy at `proxy` using `http`, resolving
+    /// `socks5` destinations with `resolver`. It goes unused for `socks5h`.
+    pub fn with_resolver(http: T, proxy: Uri, resolver: R) -> Result<Self, io::Error> {
+        SocksConnector::build(http, proxy, || resolver)
+    }
+
+    /// Makes a resolver only for `socks5`, so `socks5h` starts no threads.
+    fn build<F>(http: T, proxy: Uri, resolver: F) -> Result<Self, io::Error>
+    where
+        F: FnOnce() -> R,
+    {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let remote_dns = match proxy.scheme_str() {
+            Some("socks5") => false,
+            Some("socks5h") => true,
+            Some(scheme) => return Err(invalid(format!("unsupported proxy scheme {:?}", scheme))),
+            None => return Err(invalid("proxy URI must be absolute".to_owned())),
+        };
+        let authority = proxy
+            .authority_part()
+            .map(|a| a.as_str().to_owned())
+            .ok_or_else(|| invalid("proxy URI must be absolute".to_owned()))?;
+        let (credentials, host) = split_userinfo(&authority)?;
+        if let Some((ref user, ref password)) = credentials {
+            if user.is_empty() || user.len() > 255 || password.len() > 255 {
+                return Err(invalid(
+                    "SOCKS5 user names and passwords must be 1 to 255 bytes".to_owned(),
+                ));
+            }
+        }
+
+        let uri = format!("http://{}", host)
+            .parse::<Uri>()
+            .map_err(|e| invalid(format!("invalid proxy URI: {}", e)))?;
+        let mut proxy = Destination::try_from_uri(uri)
+            .map_err(|_| invalid("proxy URI must be absolute".to_owned()))?;
+        if proxy.port().is_none() {
+            proxy.set_port(1080);
+        }
+
+        Ok(SocksConnector {
+            http,
+            proxy,
+            credentials,
+            resolver: if remote_dns { None } else { Some(resolver()) },
+        })
+    }
+}
+
+impl<T: fmt::Debug, R> fmt::Debug for SocksConnector<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SocksConnector")
+            .field("proxy", &self.proxy)
+            .field("authenticated", &self.credentials.is_some())
+            .field("remote_dns", &self.resolver.is_none())
+            .field("http", &self.http)
+            .finish()
+    }
+}
+
+type BoxedIo<T> = Box<dyn Future<Item = T, Error = io::Error> + Send>;
+
+impl<T, R> Connect for SocksConnector<T, R>
+where
+    T: Connect<Error = io::Error>,
+    T::Future: 'static,
+    R: Resolve + Send + Sync,
+    R::Future: Send + 'static,
+{
+    type Transport = T::Transport;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = (T::Transport, Connected), Error = io::Error> + Send>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let port = dst
+            .port()
+            .unwrap_or(if dst.scheme() == "https" { 443 } else { 80 });
+        let host = dst.host().trim_start_matches('[').trim_end_matches(']');
+
+        let target: BoxedIo<Address> = match (host.parse::<IpAddr>(), &self.resolver) {
+            (Ok(ip), _) => Box::new(future::ok(Address::Ip(ip))),
+            (Err(_), None) => Box::new(future::ok(Address::Domain(host.to_owned()))),
+            (Err(_), Some(resolver)) => match host.parse::<Name>() {
+                Ok(name) => Box::new(resolver.resolve(name).and_then(move |mut addrs| {
+                    addrs.next().map(Address::Ip).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no addresses found")
+                    })
+                })),
+                Err(_) => Box::new(future::err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid destination host name",
+                ))),
+            },
+        };
+
+        let connecting = self.http.connect(self.proxy.clone());
+        let credentials = self.credentials.clone();
+        let target = target.map_err(|e| io::Error::from(Error::Dns(e)));
+        Box::new(target.and_then(move |target| {
+            connecting
+                .and_then(move |(stream, connected)| {
+                    handshake(stream, target, port, credentials).map(|stream| (stream, connected))
+                })
+                .map_err(|e| Error::Proxy(e).into())
+        }))
+    }
+}
+
+/// The destination as sent to the proxy.
+enum Address {
+    Ip(IpAddr),
+    Domain(String),
+}
+
+/// Runs the RFC 1928 greeting, RFC 1929 authentication if offered, and the
+/// CONNECT request over `stream`.
+fn handshake<T>(
+    stream: T,
+    target: Address,
+    port: u16,
+    credentials: Option<Credentials>,
+) -> BoxedIo<T>
+where
+    T: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let mut request = vec![VERSION, CMD_CONNECT, 0];
+    match target {
+        Address::Ip(IpAddr::V4(ip)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Address::Ip(IpAddr::V6(ip)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Address::Domain(ref name) => {
+            if name.len() > 255 {
+                return Box::new(future::err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "host name too long for SOCKS5",
+                )));
+            }
+            request.push(ATYP_DOMAIN);
+            request.push(name.len() as u8);
+            request.extend_from_slice(name.as_bytes());
+        }
+    }
+    request.extend_from_slice(&[(port >> 8) as u8, port as u8]);
+
+    let greeting = match credentials {
+        Some(_) => vec![VERSION, 2, NO_AUTH, USER_PASS],
+        None => vec![VERSION, 1, NO_AUTH],
+    };
+    let negotiated = write_all(stream, greeting)
+        .and_then(|(stream, _)| read_exact(stream, [0; 2]))
+        .and_then(move |(stream, reply)| -> BoxedIo<T> {
+            if reply[0] != VERSION {
+                return Box::new(future::err(socks_error("not a SOCKS5 proxy")));
+            }
+            match (reply[1], credentials) {
+                (NO_AUTH, _) => Box::new(future::ok(stream)),
+                (USER_PASS, Some((user, password))) => {
+                    let mut auth = vec![1, user.len() as u8];
+                    auth.extend_from_slice(&user);
+                    auth.push(password.len() as u8);
+                    auth.extend_from_slice(&password);
+                    Box::new(
+                        write_all(stream, auth)
+                            .and_then(|(stream, _)| read_exact(stream, [0; 2]))
+                            .and_then(|(stream, reply)| {
+                                if reply[1] == 0 {
+                                    Ok(stream)
+                                } else {
+                                    Err(socks_error("authentication failed"))
+                                }
+                            }),
+                    )
+                }
+                (NO_ACCEPTABLE_METHOD, _) => Box::new(future::err(socks_error(
+                    "no acceptable authentication method",
+                ))),
+                (method, _) => Box::new(future::err(socks_error(&format!(
+                    "unexpected authentication method {}",
+                    method
+                )))),
+            }
+        });
+
+    Box::new(
+        negotiated
+            .and_then(move |stream| write_all(stream, request))
+            .and_then(|(stream, _)| read_exact(stream, [0; 4]))
+            .and_then(|(stream, reply)| -> BoxedIo<T> {
+                if reply[0] != VERSION {
+                    return Box::new(future::err(socks_error("not a SOCKS5 proxy")));
+                }
+                if reply[1] != 0 {
+                    return Box::new(future::err(reply_error(reply[1])));
+                }
+                // Skip the bound address and port, which we have no use for.
+                let skip: BoxedIo<(T, usize)> = match reply[3] {
+                    ATYP_IPV4 => Box::new(future::ok((stream, 4 + 2))),
+                    ATYP_IPV6 => Box::new(future::ok((stream, 16 + 2))),
+                    ATYP_DOMAIN => Box::new(
+                        read_exact(stream, [0; 1])
+                            .map(|(stream, len)| (stream, len[0] as usize + 2)),
+                    ),
+                    _ => return Box::new(future::err(socks_error("malformed reply"))),
+                };
+                Box::new(skip.and_then(|(stream, len)| {
+                    read_exact(stream, vec![0; len]).map(|(stream, _)| stream)
+                }))
+            }),
+    )
+}
+
+fn socks_error(msg: &str) -> io::Error {
+    io::Error::other(format!("SOCKS5 proxy: {}", msg))
+}
+
+fn reply_error(code: u8) -> io::Error {
+    let (kind, msg) = match code {
+        1 => (io::ErrorKind::Other, "general failure"),
+        2 => (
+            io::ErrorKind::PermissionDenied,
+            "connection not allowed by ruleset",
+        ),
+        3 => (io::ErrorKind::Other, "network unreachable"),
+        4 => (io::ErrorKind::Other, "host unreachable"),
+        5 => (io::ErrorKind::ConnectionRefused, "connection refused"),
+        6 => (io::ErrorKind::TimedOut, "TTL expired"),
+        7 => (io::ErrorKind::Other, "command not supported"),
+        8 => (io::ErrorKind::Other, "address type not supported"),
+        _ => (io::ErrorKind::Other, "unknown error"),
+    };
+    io::Error::new(kind, format!("SOCKS5 proxy: {}", msg))
+}
@@ -0,0 +1,91 @@
+use futures::Future;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use tokio_timer::Timeout;
+
+use Error;
+
+/// The part of establishing a connection that a timeout covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Connecting the underlying transport, including name resolution, or
+    /// opening a tunnel through a proxy.
+    Connect,
+    /// A TLS handshake, with the destination or an HTTPS proxy.
+    Handshake,
+    /// Everything, from the first lookup to the finished handshake.
+    Total,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match *self {
+            Phase::Connect => "connect",
+            Phase::Handshake => "TLS handshake",
+            Phase::Total => "overall connection",
+        })
+    }
+}
+
+/// The error returned by `HttpsConnector` when one of its timeouts expires.
+///
+/// It reaches callers as `Error::Timeout`.
+#[derive(Debug)]
+pub struct TimeoutError {
+    phase: Phase,
+    timeout: Duration,
+}
+
+impl TimeoutError {
+    /// The phase that ran out of time.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// The timeout that expired.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} timed out after {:?}", self.phase, self.timeout)
+    }
+}
+
+impl StdError for TimeoutError {}
+
+/// Timeouts by phase; `None` waits indefinitely.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Timeouts {
+    pub connect: Option<Duration>,
+    pub handshake: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+type BoxedIo<T> = Box<dyn Future<Item = T, Error = io::Error> + Send>;
+
+/// Fails `future` with a `TimeoutError` for `phase` unless it completes
+/// within `timeout`.
+pub(crate) fn deadline<F>(future: F, timeout: Option<Duration>, phase: Phase) -> BoxedIo<F::Item>
+where
+    F: Future<Error = io::Error> + Send + 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Box::new(future),
+    };
+    Box::new(Timeout::new(future, timeout).map_err(move |e| {
+        if e.is_elapsed() {
+            Error::Timeout(TimeoutError { phase, timeout }).into()
+        } else if e.is_timer() {
+            let e = e.into_timer().unwrap();
+            Error::Io(io::Error::other(format!("{} timer failed: {}", phase, e))).into()
+        } else {
+            e.into_inner().unwrap()
+        }
+    }))
+}
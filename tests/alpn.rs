@@ -0,0 +1,69 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use futures::Future;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Client, Response, Server, Version};
+use hyper_tls_api::HttpsAcceptor;
+use std::net::SocketAddr;
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+fn serve(rt: &mut Runtime, identity: &Identity, protocols: &[&[u8]]) -> SocketAddr {
+    let acceptor = HttpsAcceptor::from(support::acceptor_with_alpn(identity, protocols));
+    let incoming = acceptor.bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = incoming.local_addr().unwrap();
+
+    let server = Server::builder(incoming)
+        .serve(|| service_fn_ok(|_| Response::new(Body::from(support::BODY))))
+        .map_err(|e| panic!("server failed: {}", e));
+    rt.spawn(server);
+    addr
+}
+
+fn version(rt: &mut Runtime, ca: &Identity, addr: SocketAddr, protocols: &[&str]) -> Version {
+    let mut connector = support::https_connector(ca);
+    connector.set_alpn_protocols(protocols).unwrap();
+    let client = Client::builder().build::<_, Body>(connector);
+    let uri = format!("https://localhost:{}/", addr.port());
+    rt.block_on(client.get(uri.parse().unwrap()))
+        .unwrap()
+        .version()
+}
+
+#[test]
+fn negotiated_h2_is_used() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let addr = serve(&mut rt, &ca.issue(&["localhost"]), &[b"h2", b"http/1.1"]);
+
+    let version = version(&mut rt, &ca, addr, &["h2", "http/1.1"]);
+    assert_eq!(version, Version::HTTP_2);
+}
+
+#[test]
+fn http1_is_used_when_server_prefers_it() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let addr = serve(&mut rt, &ca.issue(&["localhost"]), &[b"http/1.1"]);
+
+    let version = version(&mut rt, &ca, addr, &["h2", "http/1.1"]);
+    assert_eq!(version, Version::HTTP_11);
+}
+
+#[test]
+fn http1_is_used_without_alpn() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let addr = serve(&mut rt, &ca.issue(&["localhost"]), &[b"h2", b"http/1.1"]);
+
+    let version = version(&mut rt, &ca, addr, &[]);
+    assert_eq!(version, Version::HTTP_11);
+}
@@ -0,0 +1,222 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate tls_api;
+extern crate tokio;
+
+use futures::future;
+use hyper::client::connect::{Connect, Destination};
+use hyper::client::HttpConnector;
+use hyper_tls_api::{Error, HandshakeReason, HttpsConnector};
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+type MakeError = Arc<dyn Fn() -> tls_api::Error + Send + Sync>;
+
+/// A TLS backend whose handshakes fail with the errors it is given, to
+/// check how errors of backends the tests cannot run are classified.
+struct Failing(MakeError);
+
+struct FailingBuilder(());
+
+impl tls_api::TlsConnectorBuilder for FailingBuilder {
+    type Connector = Failing;
+    type Underlying = ();
+
+    fn underlying_mut(&mut self) -> &mut () {
+        &mut self.0
+    }
+
+    fn supports_alpn() -> bool {
+        false
+    }
+
+    fn set_alpn_protocols(&mut self, _: &[&[u8]]) -> tls_api::Result<()> {
+        Ok(())
+    }
+
+    fn set_verify_hostname(&mut self, _: bool) -> tls_api::Result<()> {
+        Ok(())
+    }
+
+    fn add_root_certificate(&mut self, _: tls_api::Certificate) -> tls_api::Result<&mut Self> {
+        Ok(self)
+    }
+
+    fn build(self) -> tls_api::Result<Failing> {
+        Err(tls_api::Error::new_other("built through From"))
+    }
+}
+
+impl tls_api::TlsConnector for Failing {
+    type Builder = FailingBuilder;
+
+    fn builder() -> tls_api::Result<FailingBuilder> {
+        Ok(FailingBuilder(()))
+    }
+
+    fn connect<S>(&self, _: &str, _: S) -> Result<tls_api::TlsStream<S>, tls_api::HandshakeError<S>>
+    where
+        S: Read + Write + fmt::Debug + Send + Sync + 'static,
+    {
+        Err(tls_api::HandshakeError::Failure((self.0)()))
+    }
+}
+
+/// An error displayed as a backend displays it.
+#[derive(Debug)]
+struct Message(String);
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for Message {}
+
+fn handshake_error(make: MakeError) -> Error {
+    // The TCP connection is made from the listen queue, never accepted.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!(
+        "https://localhost:{}/",
+        listener.local_addr().unwrap().port()
+    );
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    let connector = HttpsConnector::from((http, Failing(make)));
+    let dst = Destination::try_from_uri(uri.parse().unwrap()).unwrap();
+    let err = Runtime::new()
+        .unwrap()
+        .block_on(future::lazy(move || connector.connect(dst)))
+        .map(|_| ())
+        .unwrap_err();
+    Error::from(err)
+}
+
+fn message(msg: &'static str) -> Error {
+    handshake_error(Arc::new(move || {
+        tls_api::Error::new(Message(msg.to_owned()))
+    }))
+}
+
+fn reason(err: &Error) -> Option<HandshakeReason> {
+    err.handshake_failure().and_then(|f| f.reason())
+}
+
+#[test]
+fn rustls_errors() {
+    use HandshakeReason::*;
+
+    // As rustls displays them: before 0.20 its webpki errors, from 0.20 to
+    // 0.21 the same as peer certificate contents, from 0.22 its own
+    // certificate errors, and since 0.23 with context.
+    let cases = [
+        ("invalid certificate: CertExpired", Some(CertificateExpired)),
+        (
+            "invalid certificate: CertNotValidYet",
+            Some(CertificateNotYetValid),
+        ),
+        (
+            "invalid certificate: CertNotValidForName",
+            Some(HostnameMismatch),
+        ),
+        ("invalid certificate: UnknownIssuer", Some(UnknownIssuer)),
+        ("invalid certificate: BadDER", None),
+        (
+            "invalid peer certificate contents: invalid peer certificate: UnknownIssuer",
+            Some(UnknownIssuer),
+        ),
+        (
+            "invalid peer certificate contents: invalid peer certificate: CertExpired",
+            Some(CertificateExpired),
+        ),
+        (
+            "invalid peer certificate: Expired",
+            Some(CertificateExpired),
+        ),
+        (
+            "invalid peer certificate: NotValidYet",
+            Some(CertificateNotYetValid),
+        ),
+        (
+            "invalid peer certificate: NotValidForName",
+            Some(HostnameMismatch),
+        ),
+        (
+            "invalid peer certificate: UnknownIssuer",
+            Some(UnknownIssuer),
+        ),
+        ("invalid peer certificate: Revoked", None),
+        (
+            "invalid peer certificate: certificate expired: verification time 1700000000 (UNIX), \
+             but certificate is not valid after 1600000000 (100000000 seconds ago)",
+            Some(CertificateExpired),
+        ),
+        (
+            "invalid peer certificate: certificate not valid for name \"localhost\"; \
+             certificate is only valid for DnsName(\"other.test\")",
+            Some(HostnameMismatch),
+        ),
+    ];
+    for &(msg, expected) in &cases {
+        let err = message(msg);
+        assert!(err.is_certificate_error(), "{}: {:?}", msg, err);
+        assert_eq!(reason(&err), expected, "{}", msg);
+    }
+
+    let err = message("peer is incompatible: no ciphersuites in common");
+    assert!(!err.is_certificate_error());
+    assert_eq!(reason(&err), Some(NoSharedCipher));
+    let err = message("received fatal alert: ProtocolVersion");
+    assert_eq!(reason(&err), Some(ProtocolVersion));
+}
+
+#[test]
+fn schannel_codes() {
+    use HandshakeReason::*;
+
+    let cases = [
+        (0x800B_0101_u32, Some(CertificateExpired), true),
+        (0x8009_0328, Some(CertificateExpired), true),
+        (0x800B_010F, Some(HostnameMismatch), true),
+        (0x8009_0322, Some(HostnameMismatch), true),
+        (0x800B_0109, Some(UnknownIssuer), true),
+        (0x800B_010A, Some(UnknownIssuer), true),
+        (0x8009_0325, Some(UnknownIssuer), true),
+        (0x800B_010C, None, true),
+        (0x8009_0302, Some(ProtocolVersion), false),
+        (0x8009_0331, Some(NoSharedCipher), false),
+    ];
+    for &(code, expected, certificate) in &cases {
+        let code = code as i32;
+        // As an `io::Error`, and as native-tls displays one without
+        // exposing it.
+        let exposed = handshake_error(Arc::new(move || {
+            tls_api::Error::new(io::Error::from_raw_os_error(code))
+        }));
+        let wrapped = handshake_error(Arc::new(move || {
+            let msg = io::Error::from_raw_os_error(code).to_string();
+            tls_api::Error::new(Message(msg))
+        }));
+        for err in &[exposed, wrapped] {
+            assert_eq!(reason(err), expected, "{:#x}", code);
+            assert_eq!(err.is_certificate_error(), certificate, "{:#x}", code);
+        }
+    }
+}
+
+#[test]
+fn unknown_errors_are_not_diagnosed() {
+    let err = message("something else went wrong (os error 2)");
+    assert!(!err.is_certificate_error());
+    assert_eq!(reason(&err), None);
+    match err {
+        Error::Handshake(_) => {}
+        other => panic!("expected a handshake error, got {:?}", other),
+    }
+}
@@ -0,0 +1,157 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::{Error, HttpsConnector};
+use std::io;
+use std::net::TcpListener;
+use tls_api::TlsConnectorBuilder;
+
+use support::Identity;
+
+fn connector(tls: support::Connector) -> HttpsConnector<HttpConnector, support::Connector> {
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    HttpsConnector::from((http, tls))
+}
+
+fn configured_connector(ca: &Identity) -> HttpsConnector<HttpConnector, support::Connector> {
+    let ca = ca.cert.to_der().unwrap();
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    HttpsConnector::with_tls_builder(http, move |builder: &mut support::ConnectorBuilder| {
+        builder.add_root_certificate(tls_api::Certificate::from_der(ca.clone()))?;
+        Ok(())
+    })
+    .unwrap()
+}
+
+#[test]
+fn https_request_over_tls() {
+    let ca = Identity::ca();
+    let addr = support::https_server(&ca.issue(&["localhost"]), 1);
+
+    let body = support::get(
+        connector(support::connector_trusting(&ca)),
+        &format!("https://localhost:{}/", addr.port()),
+    )
+    .unwrap();
+    assert_eq!(body, support::BODY);
+}
+
+#[test]
+fn http_request_is_passed_through() {
+    let ca = Identity::ca();
+    let addr = support::http_server(1);
+
+    let body = support::get(
+        connector(support::connector_trusting(&ca)),
+        &format!("http://localhost:{}/", addr.port()),
+    )
+    .unwrap();
+    assert_eq!(body, support::BODY);
+}
+
+#[test]
+fn untrusted_certificate_is_rejected() {
+    let server_ca = Identity::ca();
+    let client_ca = Identity::ca();
+    let addr = support::https_server(&server_ca.issue(&["localhost"]), 1);
+
+    let err = support::get(
+        connector(support::connector_trusting(&client_ca)),
+        &format!("https://localhost:{}/", addr.port()),
+    )
+    .unwrap_err();
+    assert!(err.is_connect(), "unexpected error: {}", err);
+}
+
+#[test]
+fn wrong_hostname_is_rejected() {
+    let ca = Identity::ca();
+    let addr = support::https_server(&ca.issue(&["example.com"]), 1);
+
+    let err = support::get(
+        connector(support::connector_trusting(&ca)),
+        &format!("https://localhost:{}/", addr.port()),
+    )
+    .unwrap_err();
+    assert!(err.is_connect(), "unexpected error: {}", err);
+}
+
+#[test]
+fn force_https_rejects_plain_http_before_connecting() {
+    let ca = Identity::ca();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let mut connector = connector(support::connector_trusting(&ca));
+    connector.force_https(true);
+    let err = support::get(connector, &format!("http://localhost:{}/", port)).unwrap_err();
+
+    let cause = err.into_cause().expect("connect error has a cause");
+    let io = cause
+        .downcast_ref::<io::Error>()
+        .expect("cause is an io::Error");
+    assert_eq!(io.kind(), io::ErrorKind::InvalidInput);
+    match Error::from_io_ref(io) {
+        Some(Error::Policy(forced)) => assert_eq!(forced.scheme(), "http"),
+        other => panic!("expected a policy error, got {:?}", other),
+    }
+
+    let accepted = listener.accept().map(|_| ()).map_err(|e| e.kind());
+    assert_eq!(accepted, Err(io::ErrorKind::WouldBlock));
+}
+
+#[test]
+fn force_https_allows_https() {
+    let ca = Identity::ca();
+    let addr = support::https_server(&ca.issue(&["localhost"]), 1);
+
+    let mut connector = connector(support::connector_trusting(&ca));
+    connector.force_https(true);
+    let body = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap();
+    assert_eq!(body, support::BODY);
+}
+
+#[test]
+fn hostname_mismatch_is_accepted_when_verification_is_disabled() {
+    let ca = Identity::ca();
+    let addr = support::https_server(&ca.issue(&["example.com"]), 1);
+
+    let mut connector = configured_connector(&ca);
+    connector.danger_disable_hostname_verification(true);
+    let body = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap();
+    assert_eq!(body, support::BODY);
+}
+
+#[test]
+fn chain_is_verified_when_hostname_verification_is_disabled() {
+    let server_ca = Identity::ca();
+    let client_ca = Identity::ca();
+    let addr = support::https_server(&server_ca.issue(&["example.com"]), 1);
+
+    let mut connector = configured_connector(&client_ca);
+    connector.danger_disable_hostname_verification(true);
+    let err = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err();
+    assert!(err.is_connect(), "unexpected error: {}", err);
+}
+
+#[test]
+fn prebuilt_connector_keeps_hostname_verification() {
+    let ca = Identity::ca();
+    let addr = support::https_server(&ca.issue(&["example.com"]), 1);
+
+    let mut connector = connector(support::connector_trusting(&ca));
+    connector.danger_disable_hostname_verification(true);
+    assert!(format!("{:?}", connector).contains("hostname_verification: true"));
+    let err = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err();
+    assert!(err.is_connect(), "unexpected error: {}", err);
+}
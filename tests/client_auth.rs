@@ -0,0 +1,174 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::{ClientTlsHooks, HttpsConnector, Identity, RootCertificates};
+use openssl::pkcs12::Pkcs12;
+use openssl::ssl::{SslVerifyMode, SslVersion};
+use openssl::x509::store::X509StoreBuilder;
+use std::net::SocketAddr;
+use tls_api::TlsAcceptorBuilder;
+
+/// Serves one connection, requiring a client certificate issued by
+/// `client_ca`.
+fn mtls_server(identity: &support::Identity, client_ca: &support::Identity) -> SocketAddr {
+    let mut builder = support::AcceptorBuilder::new(identity).unwrap();
+    {
+        let ssl = builder.underlying_mut();
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(client_ca.cert.clone()).unwrap();
+        ssl.set_verify_cert_store(store.build()).unwrap();
+        ssl.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        // TLS 1.3 reports a rejected client certificate only after the
+        // handshake, which hyper sees as a reset rather than a failure.
+        ssl.set_max_proto_version(Some(SslVersion::TLS1_2)).unwrap();
+    }
+    support::https_server_with(builder.build().unwrap(), 1)
+}
+
+fn roots_with(ca: &support::Identity) -> RootCertificates {
+    let mut roots = RootCertificates::empty();
+    roots.add_pem(&ca.cert.to_pem().unwrap()).unwrap();
+    roots
+}
+
+#[test]
+fn pkcs12_identity_is_presented() {
+    let ca = support::Identity::ca();
+    let client = ca.issue(&["client"]);
+    let addr = mtls_server(&ca.issue(&["localhost"]), &ca);
+
+    let archive = Pkcs12::builder()
+        .name("client")
+        .pkey(&client.key)
+        .cert(&client.cert)
+        .build2("secret")
+        .unwrap();
+    let identity = Identity::from_pkcs12(&archive.to_der().unwrap(), "secret");
+    let connector = HttpsConnector::<_, support::Connector>::with_identity_and_roots(
+        1,
+        Some(identity),
+        roots_with(&ca),
+        support::client_hooks(),
+    )
+    .unwrap();
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn pem_identity_is_presented() {
+    let ca = support::Identity::ca();
+    let client = ca.issue(&["client"]);
+    let addr = mtls_server(&ca.issue(&["localhost"]), &ca);
+
+    let identity = Identity::from_pem(
+        &client.cert.to_pem().unwrap(),
+        &client.key.private_key_to_pem_pkcs8().unwrap(),
+    )
+    .unwrap();
+    let connector = HttpsConnector::<_, support::Connector>::with_identity_and_roots(
+        1,
+        Some(identity),
+        roots_with(&ca),
+        support::client_hooks(),
+    )
+    .unwrap();
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+}
+
+/// Like `roots_with`, but on top of the default roots, which needs no hooks.
+fn system_roots_and(ca: &support::Identity) -> RootCertificates {
+    let mut roots = RootCertificates::system();
+    roots.add_der(&ca.cert.to_der().unwrap());
+    roots
+}
+
+#[test]
+fn missing_identity_is_rejected() {
+    let ca = support::Identity::ca();
+    let addr = mtls_server(&ca.issue(&["localhost"]), &ca);
+
+    let connector =
+        HttpsConnector::<_, support::Connector>::with_root_certificates(1, system_roots_and(&ca))
+            .unwrap();
+    assert!(support::get(connector, &format!("https://localhost:{}/", addr.port())).is_err());
+}
+
+#[test]
+fn custom_roots_are_trusted() {
+    let ca = support::Identity::ca();
+    let addr = support::https_server(&ca.issue(&["localhost"]), 1);
+
+    let connector =
+        HttpsConnector::<_, support::Connector>::with_root_certificates(1, system_roots_and(&ca))
+            .unwrap();
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn empty_roots_trust_nothing() {
+    let ca = support::Identity::ca();
+    let addr = support::https_server(&ca.issue(&["localhost"]), 1);
+
+    let connector = HttpsConnector::<_, support::Connector>::with_identity_and_roots(
+        1,
+        None,
+        RootCertificates::empty(),
+        support::client_hooks(),
+    )
+    .unwrap();
+    assert!(support::get(connector, &format!("https://localhost:{}/", addr.port())).is_err());
+}
+
+#[test]
+fn missing_hooks_are_named() {
+    let ca = support::Identity::ca();
+    let client = ca.issue(&["client"]);
+    let identity = Identity::from_pem(
+        &client.cert.to_pem().unwrap(),
+        &client.key.private_key_to_pem_pkcs8().unwrap(),
+    )
+    .unwrap();
+
+    let err = HttpsConnector::<HttpConnector, support::Connector>::with_identity(
+        1,
+        identity,
+        ClientTlsHooks::new(),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("on_set_identity"), "{}", err);
+
+    let err = HttpsConnector::<HttpConnector, support::Connector>::with_root_certificates(
+        1,
+        roots_with(&ca),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("on_clear_roots"), "{}", err);
+}
+
+#[test]
+fn malformed_pem_is_rejected() {
+    let ca = support::Identity::ca();
+    let key = ca.key.private_key_to_pem_pkcs8().unwrap();
+    let cert = ca.cert.to_pem().unwrap();
+
+    assert!(Identity::from_pem(&key, &key).is_err());
+    assert!(Identity::from_pem(&cert, &cert).is_err());
+    assert!(Identity::from_pem(b"-----BEGIN CERTIFICATE-----\nAAAA\n", &key).is_err());
+    assert!(RootCertificates::empty().add_pem(&key).is_err());
+}
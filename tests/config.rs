@@ -0,0 +1,188 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate serde;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::{Error, HttpsConnector, IdentityConfig, SpkiHash, TlsClientConfig};
+use openssl::ssl::{SslVerifyMode, SslVersion};
+use openssl::x509::store::X509StoreBuilder;
+use serde::de::value::{self, MapDeserializer};
+use serde::Deserialize;
+use std::io;
+use std::path::PathBuf;
+use tls_api::TlsAcceptorBuilder;
+
+use support::{Identity, TempDir};
+
+fn trusting(dir: &TempDir, ca: &Identity) -> TlsClientConfig {
+    TlsClientConfig {
+        ca_files: vec![dir.file(&ca.cert.to_pem().unwrap())],
+        system_roots: false,
+        ..TlsClientConfig::default()
+    }
+}
+
+fn connector(config: &TlsClientConfig) -> HttpsConnector<HttpConnector, support::Connector> {
+    HttpsConnector::from_config(config, support::client_hooks()).unwrap()
+}
+
+#[test]
+fn missing_fields_take_defaults() {
+    let fields: Vec<(&str, &str)> = Vec::new();
+    let de = MapDeserializer::<_, value::Error>::new(fields.into_iter());
+    let config = TlsClientConfig::deserialize(de).unwrap();
+    assert_eq!(config, TlsClientConfig::default());
+    assert!(config.verify_hostname);
+    assert!(config.system_roots);
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    let fields = vec![("verify_hostnames", "false")];
+    let de = MapDeserializer::<_, value::Error>::new(fields.into_iter());
+    let err = TlsClientConfig::deserialize(de).unwrap_err();
+    assert!(err.to_string().contains("verify_hostnames"), "{}", err);
+}
+
+#[test]
+fn ca_files_are_trusted() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 2);
+    let uri = format!("https://localhost:{}/", server.port());
+
+    assert_eq!(
+        support::get(connector(&trusting(&dir, &ca)), &uri).unwrap(),
+        support::BODY
+    );
+    let err = support::get(connector(&trusting(&dir, &Identity::ca())), &uri).unwrap_err();
+    assert!(support::connect_error(err).is_certificate_error());
+}
+
+#[test]
+fn identity_is_presented() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let mut builder = support::AcceptorBuilder::new(&ca.issue(&["localhost"])).unwrap();
+    {
+        let ssl = builder.underlying_mut();
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(ca.cert.clone()).unwrap();
+        ssl.set_verify_cert_store(store.build()).unwrap();
+        ssl.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        ssl.set_max_proto_version(Some(SslVersion::TLS1_2)).unwrap();
+    }
+    let server = support::https_server_with(builder.build().unwrap(), 1);
+    let uri = format!("https://localhost:{}/", server.port());
+
+    let client = ca.issue(&["client"]);
+    let config = TlsClientConfig {
+        identity: Some(IdentityConfig::Pem {
+            certificate: dir.file(&client.cert.to_pem().unwrap()),
+            private_key: dir.file(&client.key.private_key_to_pem_pkcs8().unwrap()),
+        }),
+        ..trusting(&dir, &ca)
+    };
+    assert_eq!(
+        support::get(connector(&config), &uri).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn pins_are_enforced() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let server = support::https_server(&leaf, 2);
+    let uri = format!("https://localhost:{}/", server.port());
+    // The chain is reported, so pins hold with TLS 1.3 too.
+    let pinned = |hash: SpkiHash| {
+        let mut config = trusting(&dir, &ca);
+        config
+            .pins
+            .insert("localhost".to_owned(), vec![hash.to_string()]);
+        let mut hooks = support::client_hooks();
+        hooks.on_build(|builder: &mut support::ConnectorBuilder| {
+            support::report_verification(builder);
+            Ok(())
+        });
+        HttpsConnector::<_, support::Connector>::from_config(&config, hooks).unwrap()
+    };
+
+    let leaf_hash = SpkiHash::of_certificate(&leaf.cert.to_der().unwrap()).unwrap();
+    assert_eq!(
+        support::get(pinned(leaf_hash), &uri).unwrap(),
+        support::BODY
+    );
+
+    let other = SpkiHash::of_certificate(&ca.issue(&["other"]).cert.to_der().unwrap()).unwrap();
+    let err = support::get(pinned(other), &uri).unwrap_err();
+    match support::connect_error(err) {
+        Error::Pinning(_) => {}
+        other => panic!("expected a pinning error, got {:?}", other),
+    }
+}
+
+#[test]
+fn proxy_is_used() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let uri = format!("https://localhost:{}/", server.port());
+    let proxy = support::proxy_server(1);
+
+    let config = TlsClientConfig {
+        proxy: Some(format!("http://{}", proxy.addr)),
+        ..trusting(&dir, &ca)
+    };
+    assert_eq!(
+        support::get(connector(&config), &uri).unwrap(),
+        support::BODY
+    );
+    assert_eq!(proxy.heads().len(), 1);
+}
+
+#[test]
+fn invalid_settings_are_reported() {
+    let config = TlsClientConfig {
+        ca_files: vec![PathBuf::from("/nonexistent/ca.pem")],
+        ..TlsClientConfig::default()
+    };
+    let err = HttpsConnector::<HttpConnector, support::Connector>::from_config(
+        &config,
+        support::client_hooks(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(err.to_string().contains("/nonexistent/ca.pem"), "{}", err);
+
+    let mut config = TlsClientConfig::default();
+    config
+        .pins
+        .insert("example.com".to_owned(), vec!["not a pin".to_owned()]);
+    let err = HttpsConnector::<HttpConnector, support::Connector>::from_config(
+        &config,
+        support::client_hooks(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("example.com"), "{}", err);
+
+    let config = TlsClientConfig {
+        dns_threads: 0,
+        ..TlsClientConfig::default()
+    };
+    let err = HttpsConnector::<HttpConnector, support::Connector>::from_config(
+        &config,
+        support::client_hooks(),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "dns_threads must be at least 1");
+}
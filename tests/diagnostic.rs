@@ -0,0 +1,205 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate net2;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use futures::future;
+use hyper::client::HttpConnector;
+use hyper_tls_api::{Error, HandshakeReason, HttpsConnector};
+use net2::TcpStreamExt;
+use openssl::ssl::{SslConnector, SslMethod, SslVersion};
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tls_api::{TlsAcceptorBuilder, TlsConnectorBuilder};
+use tokio::reactor::Handle;
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+/// The reason `err` gives, checking it names `localhost:port`.
+fn reason(err: &Error, port: u16) -> HandshakeReason {
+    let failure = err.handshake_failure().expect("a handshake failure");
+    assert_eq!(failure.host(), Some("localhost"));
+    assert_eq!(failure.port(), Some(port));
+    let reason = failure.reason().expect("a known reason");
+    let expected = format!("TLS handshake with localhost:{} failed: {}", port, reason);
+    assert_eq!(err.to_string(), expected);
+    reason
+}
+
+fn certificate_reason(leaf: &Identity, ca: &Identity) -> HandshakeReason {
+    let server = support::https_server(leaf, 1);
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::connect_error(support::get(support::https_connector(ca), &uri).unwrap_err());
+    assert!(err.is_certificate_error());
+    reason(&err, server.port())
+}
+
+#[test]
+fn expired_certificate() {
+    let ca = Identity::ca();
+    let leaf = ca.issue_valid(&["localhost"], -30, -1);
+    assert_eq!(
+        certificate_reason(&leaf, &ca),
+        HandshakeReason::CertificateExpired
+    );
+}
+
+#[test]
+fn certificate_not_yet_valid() {
+    let ca = Identity::ca();
+    let leaf = ca.issue_valid(&["localhost"], 1, 30);
+    assert_eq!(
+        certificate_reason(&leaf, &ca),
+        HandshakeReason::CertificateNotYetValid
+    );
+}
+
+#[test]
+fn unknown_issuer() {
+    let self_signed = Identity::ca();
+    assert_eq!(
+        certificate_reason(&self_signed, &Identity::ca()),
+        HandshakeReason::UnknownIssuer
+    );
+}
+
+#[test]
+fn hostname_mismatch() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["other.test"]);
+    assert_eq!(
+        certificate_reason(&leaf, &ca),
+        HandshakeReason::HostnameMismatch
+    );
+}
+
+#[test]
+fn reported_verify_error_is_kept() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue_valid(&["localhost"], -30, -1), 1);
+    let uri = format!("https://localhost:{}/", server.port());
+    let err =
+        support::connect_error(support::get(support::https_connector(&ca), &uri).unwrap_err());
+    let failure = err.handshake_failure().expect("a handshake failure");
+    assert_eq!(failure.verify_error(), Some("certificate has expired"));
+}
+
+#[test]
+fn unreported_rejection_is_still_a_verification_error() {
+    let ca = Identity::ca();
+    let server = support::https_server(&Identity::ca().issue(&["localhost"]), 1);
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    let connector = HttpsConnector::from((http, support::connector_trusting(&ca)));
+
+    // OpenSSL's own error says that verification failed, but not why.
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::connect_error(support::get(connector, &uri).unwrap_err());
+    match err {
+        Error::Verification(ref failure) => {
+            assert_eq!(failure.verify_error(), None);
+            assert_eq!(failure.reason(), None);
+        }
+        ref other => panic!("expected a verification error, got {:?}", other),
+    }
+}
+
+#[test]
+fn protocol_version_mismatch() {
+    let ca = Identity::ca();
+    let mut acceptor = support::AcceptorBuilder::new(&ca.issue(&["localhost"])).unwrap();
+    acceptor
+        .underlying_mut()
+        .set_max_proto_version(Some(SslVersion::TLS1_2))
+        .unwrap();
+    let server = support::https_server_with(acceptor.build().unwrap(), 1);
+
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    let connector = HttpsConnector::<_, support::Connector>::with_tls_builder(
+        http,
+        |builder: &mut support::ConnectorBuilder| {
+            builder
+                .underlying_mut()
+                .set_min_proto_version(Some(SslVersion::TLS1_3))
+                .map_err(tls_api::Error::new)
+        },
+    )
+    .unwrap();
+
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::connect_error(support::get(connector, &uri).unwrap_err());
+    assert!(!err.is_retryable());
+    assert_eq!(
+        reason(&err, server.port()),
+        HandshakeReason::ProtocolVersion
+    );
+}
+
+#[test]
+fn peer_reset_during_handshake() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (mut tcp, _) = listener.accept().unwrap();
+        let _ = tcp.read(&mut [0; 16]);
+        TcpStreamExt::set_linger(&tcp, Some(Duration::from_secs(0))).unwrap();
+    });
+
+    let uri = format!("https://localhost:{}/", port);
+    let err = support::connect_error(
+        support::get(support::https_connector(&Identity::ca()), &uri).unwrap_err(),
+    );
+    assert!(err.is_retryable());
+    assert_eq!(reason(&err, port), HandshakeReason::PeerReset);
+}
+
+#[test]
+fn no_shared_cipher_when_accepting() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut client = SslConnector::builder(SslMethod::tls()).unwrap();
+        client
+            .set_min_proto_version(Some(SslVersion::TLS1_3))
+            .unwrap();
+        client
+            .set_ciphersuites("TLS_CHACHA20_POLY1305_SHA256")
+            .unwrap();
+        let tcp = TcpStream::connect(addr).unwrap();
+        let _ = client.build().connect("localhost", tcp);
+    });
+    let (tcp, _) = listener.accept().unwrap();
+    let tcp = tokio::net::TcpStream::from_std(tcp, &Handle::default()).unwrap();
+
+    let mut acceptor =
+        support::AcceptorBuilder::new(&Identity::ca().issue(&["localhost"])).unwrap();
+    acceptor
+        .underlying_mut()
+        .set_ciphersuites("TLS_AES_256_GCM_SHA384")
+        .unwrap();
+    let acceptor = acceptor.build().unwrap();
+    let err = Runtime::new()
+        .unwrap()
+        .block_on(future::lazy(move || {
+            hyper_tls_api::accept_async(&acceptor, tcp)
+        }))
+        .map(|_| ())
+        .unwrap_err();
+
+    let failure = err.handshake_failure().expect("a handshake failure");
+    assert_eq!(failure.reason(), Some(HandshakeReason::NoSharedCipher));
+    assert_eq!(failure.host(), None);
+    assert_eq!(
+        err.to_string(),
+        "TLS handshake failed: no cipher suite in common"
+    );
+}
@@ -0,0 +1,159 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate net2;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use futures::future;
+use hyper_tls_api::Error;
+use net2::TcpStreamExt;
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tokio::reactor::Handle;
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+fn closed_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Accepts one connection, from a client that runs `client` against it.
+fn accept_error<F>(client: F) -> Error
+where
+    F: FnOnce(TcpStream) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || client(TcpStream::connect(addr).unwrap()));
+    let (tcp, _) = listener.accept().unwrap();
+    let tcp = tokio::net::TcpStream::from_std(tcp, &Handle::default()).unwrap();
+
+    let acceptor = support::acceptor(&Identity::ca().issue(&["localhost"]));
+    Runtime::new()
+        .unwrap()
+        .block_on(future::lazy(move || {
+            hyper_tls_api::accept_async(&acceptor, tcp)
+        }))
+        .map(|_| ())
+        .unwrap_err()
+}
+
+#[test]
+fn refused_connection_is_a_connect_error() {
+    let ca = Identity::ca();
+    let uri = format!("https://localhost:{}/", closed_port());
+    let err =
+        support::connect_error(support::get(support::https_connector(&ca), &uri).unwrap_err());
+    assert!(err.is_retryable());
+    assert!(!err.is_certificate_error());
+    assert!(!err.is_timeout());
+    match err {
+        Error::Connect(e) => assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused),
+        other => panic!("expected a connect error, got {:?}", other),
+    }
+}
+
+#[test]
+fn untrusted_certificate_is_a_verification_error() {
+    let server = support::https_server(&Identity::ca().issue(&["localhost"]), 1);
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::connect_error(
+        support::get(support::https_connector(&Identity::ca()), &uri).unwrap_err(),
+    );
+    assert!(!err.is_retryable());
+    assert!(err.is_certificate_error());
+    assert!(!err.is_timeout());
+    match err {
+        Error::Verification(_) => {}
+        other => panic!("expected a verification error, got {:?}", other),
+    }
+}
+
+#[test]
+fn expired_timeout_is_retryable() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!(
+        "https://localhost:{}/",
+        listener.local_addr().unwrap().port()
+    );
+    let mut connector = support::https_connector(&Identity::ca());
+    connector.set_handshake_timeout(Some(Duration::from_millis(100)));
+
+    let err = support::connect_error(support::get(connector, &uri).unwrap_err());
+    assert!(err.is_retryable());
+    assert!(!err.is_certificate_error());
+    assert!(err.is_timeout());
+}
+
+#[test]
+fn reset_mid_handshake_is_retryable() {
+    let err = accept_error(|tcp| {
+        TcpStreamExt::set_linger(&tcp, Some(Duration::from_secs(0))).unwrap();
+    });
+    assert!(err.is_retryable(), "{:?}", err);
+    assert!(!err.is_certificate_error());
+    assert!(!err.is_timeout());
+    match err {
+        Error::Handshake(_) => {}
+        other => panic!("expected a handshake error, got {:?}", other),
+    }
+}
+
+#[test]
+fn protocol_error_is_fatal() {
+    let err = accept_error(|mut tcp| {
+        tcp.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        thread::sleep(Duration::from_secs(1));
+    });
+    assert!(!err.is_retryable(), "{:?}", err);
+    assert!(!err.is_certificate_error());
+    assert!(!err.is_timeout());
+    match err {
+        Error::Handshake(_) => {}
+        other => panic!("expected a handshake error, got {:?}", other),
+    }
+}
+
+#[test]
+fn refused_tunnel_is_a_proxy_error() {
+    let ca = Identity::ca();
+    let proxy = support::proxy_server_with(1, |_| Some("HTTP/1.1 403 Forbidden"));
+
+    let mut connector = support::https_connector(&ca);
+    let proxy_uri = format!("http://{}", proxy.addr).parse().unwrap();
+    connector.set_proxy(Some(proxy_uri)).unwrap();
+    match support::connect_error(support::get(connector, "https://localhost:1/").unwrap_err()) {
+        Error::Proxy(e) => assert!(e.to_string().contains("403 Forbidden"), "{}", e),
+        other => panic!("expected a proxy error, got {:?}", other),
+    }
+}
+
+#[test]
+fn io_error_round_trip_is_lossless() {
+    let err = io::Error::from(Error::Connect(io::ErrorKind::ConnectionReset.into()));
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    assert!(Error::from_io_ref(&err).is_some());
+    match Error::from(err) {
+        Error::Connect(e) => assert_eq!(e.kind(), io::ErrorKind::ConnectionReset),
+        other => panic!("expected a connect error, got {:?}", other),
+    }
+
+    let foreign = io::Error::other("not ours");
+    assert!(Error::from_io_ref(&foreign).is_none());
+    match Error::from(foreign) {
+        Error::Io(e) => assert_eq!(e.to_string(), "not ours"),
+        other => panic!("expected an io error, got {:?}", other),
+    }
+}
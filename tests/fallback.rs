@@ -0,0 +1,100 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::{Error, HttpsConnector, StaticResolver};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use support::Identity;
+
+const GOOD: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const BAD: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+const CLOSED: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3));
+
+/// A server on `GOOD` trusted by `ca`, and one on the same port of `BAD`
+/// presenting a certificate from another CA.
+fn servers(ca: &Identity) -> u16 {
+    let good = support::https_server(&ca.issue(&["backend.test"]), 1);
+    let untrusted = Identity::ca().issue(&["backend.test"]);
+    let addr = SocketAddr::new(BAD, good.port());
+    support::https_server_at(addr, support::acceptor(&untrusted), 1);
+    good.port()
+}
+
+fn connector(
+    ca: &Identity,
+    addrs: &[IpAddr],
+    max_attempts: usize,
+) -> HttpsConnector<HttpConnector, support::Connector> {
+    let mut resolver = StaticResolver::new();
+    for &addr in addrs {
+        resolver.insert("backend.test", addr);
+    }
+    let mut connector = support::https_connector(ca);
+    connector.set_address_fallback(resolver, max_attempts);
+    connector
+}
+
+fn failed_addresses(err: hyper::Error) -> Vec<IpAddr> {
+    let cause = err.into_cause().expect("connect error has a cause");
+    let io = cause
+        .downcast_ref::<io::Error>()
+        .expect("connect error is an io::Error");
+    match Error::from_io_ref(io) {
+        Some(Error::Attempts(attempts)) => {
+            attempts.failures().iter().map(|&(addr, _)| addr).collect()
+        }
+        other => panic!("expected every attempt to fail, got {:?}", other),
+    }
+}
+
+#[test]
+fn failed_handshake_falls_through() {
+    let ca = Identity::ca();
+    let port = servers(&ca);
+
+    let connector = connector(&ca, &[BAD, GOOD], 3);
+    assert_eq!(
+        support::get(connector, &format!("https://backend.test:{}/", port)).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn refused_connection_falls_through() {
+    let ca = Identity::ca();
+    let port = servers(&ca);
+
+    let connector = connector(&ca, &[CLOSED, GOOD], 3);
+    assert_eq!(
+        support::get(connector, &format!("https://backend.test:{}/", port)).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn every_failure_is_reported() {
+    let ca = Identity::ca();
+    let port = servers(&ca);
+
+    let connector = connector(&ca, &[CLOSED, BAD], 3);
+    let err = support::get(connector, &format!("https://backend.test:{}/", port)).unwrap_err();
+    assert_eq!(failed_addresses(err), vec![CLOSED, BAD]);
+}
+
+#[test]
+fn attempts_are_capped() {
+    let ca = Identity::ca();
+    let port = servers(&ca);
+
+    let connector = connector(&ca, &[CLOSED, BAD, GOOD], 2);
+    let err = support::get(connector, &format!("https://backend.test:{}/", port)).unwrap_err();
+    assert_eq!(failed_addresses(err), vec![CLOSED, BAD]);
+}
@@ -0,0 +1,153 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate net2;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::connect::{Connect, Destination};
+use hyper_tls_api::{Error, HappyEyeballsConnector, HttpsConnector, StaticResolver};
+use net2::TcpBuilder;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+use tls_api::TlsConnectorBuilder;
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+const V4: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const V6: IpAddr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+
+/// A listener that never completes a handshake, standing in for a
+/// black-holed route: its accept queue is full, so SYNs go unanswered.
+struct BlackHole {
+    _listener: TcpListener,
+    _parked: TcpStream,
+}
+
+fn black_hole(addr: SocketAddr) -> BlackHole {
+    let builder = match addr {
+        SocketAddr::V4(_) => TcpBuilder::new_v4(),
+        SocketAddr::V6(_) => TcpBuilder::new_v6(),
+    }
+    .unwrap();
+    let listener = builder.bind(addr).unwrap().listen(0).unwrap();
+    let parked = TcpStream::connect(addr).unwrap();
+    let blocked = TcpStream::connect_timeout(&addr, Duration::from_millis(200));
+    assert!(blocked.is_err(), "the accept queue is not full");
+    BlackHole {
+        _listener: listener,
+        _parked: parked,
+    }
+}
+
+fn resolver(host: &str, addrs: &[IpAddr]) -> StaticResolver {
+    let mut resolver = StaticResolver::new();
+    for &addr in addrs {
+        resolver.insert(host, addr);
+    }
+    resolver
+}
+
+fn connect(
+    connector: &HappyEyeballsConnector<StaticResolver>,
+    port: u16,
+) -> io::Result<SocketAddr> {
+    let uri = format!("http://backend.test:{}/", port).parse().unwrap();
+    Runtime::new()
+        .unwrap()
+        .block_on(connector.connect(Destination::try_from_uri(uri).unwrap()))
+        .map(|(tcp, _)| tcp.peer_addr().unwrap())
+}
+
+#[test]
+fn black_holed_ipv6_falls_back_to_ipv4() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["backend.test"]), 1);
+    let _hole = black_hole(SocketAddr::new(V6, server.port()));
+
+    let mut http = HappyEyeballsConnector::with_resolver(resolver("backend.test", &[V6, V4]));
+    http.set_attempt_delay(Duration::from_millis(100));
+    let der = ca.cert.to_der().unwrap();
+    let connector = HttpsConnector::<_, support::Connector>::with_tls_builder(
+        http,
+        move |builder: &mut support::ConnectorBuilder| {
+            builder.add_root_certificate(tls_api::Certificate::from_der(der.clone()))?;
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let started = Instant::now();
+    let uri = format!("https://backend.test:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn first_family_is_tried_first() {
+    let v4 = TcpListener::bind((V4, 0)).unwrap();
+    let port = v4.local_addr().unwrap().port();
+    let _v6 = TcpListener::bind((V6, port)).unwrap();
+
+    let connector = HappyEyeballsConnector::with_resolver(resolver("backend.test", &[V4, V6]));
+    assert_eq!(connect(&connector, port).unwrap().ip(), V4);
+
+    let connector = HappyEyeballsConnector::with_resolver(resolver("backend.test", &[V6, V4]));
+    assert_eq!(connect(&connector, port).unwrap().ip(), V6);
+}
+
+#[test]
+fn refused_attempt_starts_the_next_at_once() {
+    let v4 = TcpListener::bind((V4, 0)).unwrap();
+    let port = v4.local_addr().unwrap().port();
+
+    let mut connector = HappyEyeballsConnector::with_resolver(resolver("backend.test", &[V6, V4]));
+    connector.set_attempt_delay(Duration::from_secs(30));
+    let started = Instant::now();
+    assert_eq!(connect(&connector, port).unwrap().ip(), V4);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn every_attempt_failing_is_an_error() {
+    let port = TcpListener::bind((V4, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let connector = HappyEyeballsConnector::with_resolver(resolver("backend.test", &[V6, V4]));
+    let err = connect(&connector, port).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+}
+
+#[test]
+fn failed_lookup_is_a_dns_error() {
+    let connector = HappyEyeballsConnector::with_resolver(StaticResolver::new());
+    let err = connect(&connector, 443).unwrap_err();
+    match Error::from_io_ref(&err) {
+        Some(err @ Error::Dns(_)) => assert!(!err.is_retryable()),
+        other => panic!("expected a DNS error, got {:?}", other),
+    }
+}
+
+#[test]
+fn failed_system_lookup_is_not_retryable() {
+    // getaddrinfo's failures reach Rust without a telling error kind.
+    let connector = HappyEyeballsConnector::new(1);
+    let uri = "http://nonexistent.invalid/".parse().unwrap();
+    let err = Runtime::new()
+        .unwrap()
+        .block_on(connector.connect(Destination::try_from_uri(uri).unwrap()))
+        .map(|_| ())
+        .unwrap_err();
+    match Error::from(err) {
+        err @ Error::Dns(_) => assert!(!err.is_retryable(), "{:?}", err),
+        other => panic!("expected a DNS error, got {:?}", other),
+    }
+}
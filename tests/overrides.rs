@@ -0,0 +1,95 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use support::Identity;
+
+#[test]
+fn resolve_connects_to_the_given_address() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["backend.test"]), 1);
+
+    let mut connector = support::https_connector(&ca);
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, server.port()));
+    connector.resolve("backend.test", 443, addr);
+    assert_eq!(
+        support::get(connector, "https://backend.test/").unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn connect_to_keeps_the_original_name_for_tls() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["backend.test"]), 1);
+
+    let mut connector = support::https_connector(&ca);
+    connector
+        .connect_to("Backend.Test", 8443, "localhost", server.port())
+        .unwrap();
+    assert_eq!(
+        support::get(connector, "https://backend.test:8443/").unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn connect_to_target_is_resolved_by_resolve() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["backend.test"]), 1);
+
+    let mut connector = support::https_connector(&ca);
+    connector
+        .connect_to("backend.test", 443, "node1.test", 8443)
+        .unwrap();
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, server.port()));
+    connector.resolve("node1.test", 8443, addr);
+    assert_eq!(
+        support::get(connector, "https://backend.test/").unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn connect_to_redirects_the_tunnel() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["backend.test"]), 1);
+    let proxy = support::proxy_server(1);
+
+    let mut connector = support::https_connector(&ca);
+    connector
+        .set_proxy(Some(format!("http://{}", proxy.addr).parse().unwrap()))
+        .unwrap();
+    connector
+        .connect_to("backend.test", 443, "localhost", server.port())
+        .unwrap();
+    assert_eq!(
+        support::get(connector, "https://backend.test/").unwrap(),
+        support::BODY
+    );
+
+    let expected = format!("CONNECT localhost:{} HTTP/1.1\r\n", server.port());
+    assert!(
+        proxy.heads()[0].starts_with(&expected),
+        "{:?}",
+        proxy.heads()
+    );
+}
+
+#[test]
+fn invalid_connect_to_target_is_rejected() {
+    let ca = Identity::ca();
+    let mut connector = support::https_connector(&ca);
+    let err = connector
+        .connect_to("backend.test", 443, "bad host", 443)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,247 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::{HttpsConnector, PeerCertificate, SpkiHash};
+use openssl::ssl::SslVersion;
+use std::net::SocketAddr;
+use tls_api::TlsAcceptorBuilder;
+
+use support::Identity;
+
+fn server(identity: &Identity, max_version: SslVersion) -> SocketAddr {
+    let mut builder = support::AcceptorBuilder::new(identity).unwrap();
+    builder
+        .underlying_mut()
+        .set_max_proto_version(Some(max_version))
+        .unwrap();
+    support::https_server_with(builder.build().unwrap(), 1)
+}
+
+/// A TLS 1.2 server that sends `extra` after its own certificate.
+fn server_appending(identity: &Identity, extra: &Identity) -> SocketAddr {
+    let mut builder = support::AcceptorBuilder::new(identity).unwrap();
+    let underlying = builder.underlying_mut();
+    underlying
+        .set_max_proto_version(Some(SslVersion::TLS1_2))
+        .unwrap();
+    underlying.add_extra_chain_cert(extra.cert.clone()).unwrap();
+    support::https_server_with(builder.build().unwrap(), 1)
+}
+
+/// A connector that trusts `ca` without reporting the chain it verifies,
+/// so pins are checked against the chain read off the wire.
+fn unreported_connector(ca: &Identity) -> HttpsConnector<HttpConnector, support::Connector> {
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    HttpsConnector::from((http, support::connector_trusting(ca)))
+}
+
+fn spki_hash(identity: &Identity) -> SpkiHash {
+    let spki = identity
+        .cert
+        .public_key()
+        .unwrap()
+        .public_key_to_der()
+        .unwrap();
+    SpkiHash(openssl::sha::sha256(&spki))
+}
+
+#[test]
+fn matching_pin_is_accepted() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, SslVersion::TLS1_2);
+
+    let mut connector = support::https_connector(&ca);
+    connector.pin("localhost", &[SpkiHash([0; 32]), spki_hash(&leaf)]);
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn mismatch_reports_observed_hashes() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, SslVersion::TLS1_2);
+
+    let mut connector = support::https_connector(&ca);
+    connector.pin("localhost", &[SpkiHash([0; 32])]);
+    let (host, observed) = support::pinning_error(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err(),
+    );
+    assert_eq!(host, "localhost");
+    assert_eq!(observed, vec![spki_hash(&leaf), spki_hash(&ca)]);
+}
+
+#[test]
+fn report_only_mismatch_is_allowed() {
+    let ca = Identity::ca();
+    let addr = server(&ca.issue(&["localhost"]), SslVersion::TLS1_2);
+
+    let mut connector = support::https_connector(&ca);
+    connector.pin("localhost", &[SpkiHash([0; 32])]);
+    connector.set_pin_report_only(true);
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn exact_pin_overrides_wildcards() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, SslVersion::TLS1_2);
+
+    let mut connector = support::https_connector(&ca);
+    connector.pin("*", &[SpkiHash([0; 32])]);
+    connector.pin("localhost", &[spki_hash(&leaf)]);
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn unrelated_pins_do_not_apply() {
+    let ca = Identity::ca();
+    let addr = server(&ca.issue(&["localhost"]), SslVersion::TLS1_2);
+
+    let mut connector = support::https_connector(&ca);
+    connector.pin("example.com", &[SpkiHash([0; 32])]);
+    connector.pin("*.localhost", &[SpkiHash([0; 32])]);
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn reported_chain_is_checked_on_tls13() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, SslVersion::TLS1_3);
+
+    let mut connector = support::https_connector(&ca);
+    connector.pin("localhost", &[spki_hash(&leaf)]);
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+
+    let addr = server(&leaf, SslVersion::TLS1_3);
+    let mut connector = support::https_connector(&ca);
+    connector.pin("localhost", &[SpkiHash([0; 32])]);
+    let (_, observed) = support::pinning_error(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err(),
+    );
+    assert_eq!(observed, vec![spki_hash(&leaf), spki_hash(&ca)]);
+}
+
+#[test]
+fn unreported_tls13_chain_fails_closed() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, SslVersion::TLS1_3);
+
+    let mut connector = unreported_connector(&ca);
+    connector.pin("localhost", &[spki_hash(&leaf)]);
+    let (_, observed) = support::pinning_error(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err(),
+    );
+    assert!(observed.is_empty());
+}
+
+#[test]
+fn appended_pinned_certificate_does_not_match() {
+    // A certificate for the host from a trusted CA, and the pinned one,
+    // which is public, appended to the chain.
+    let ca = Identity::ca();
+    let pinned = ca.issue(&["localhost"]);
+    let other = ca.issue(&["localhost"]);
+
+    for &reported in &[false, true] {
+        let addr = server_appending(&other, &pinned);
+        let mut connector = if reported {
+            support::https_connector(&ca)
+        } else {
+            unreported_connector(&ca)
+        };
+        connector.pin("localhost", &[spki_hash(&pinned)]);
+        let (_, observed) = support::pinning_error(
+            support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err(),
+        );
+        assert_eq!(observed[0], spki_hash(&other));
+        // Read off the wire, the appended certificate is seen but not trusted.
+        assert_eq!(observed.contains(&spki_hash(&pinned)), !reported);
+    }
+}
+
+#[test]
+fn issuer_pin_needs_a_reported_chain() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+
+    let addr = server_appending(&leaf, &ca);
+    let mut connector = support::https_connector(&ca);
+    connector.pin("localhost", &[spki_hash(&ca)]);
+    assert_eq!(
+        support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap(),
+        support::BODY
+    );
+
+    let addr = server_appending(&leaf, &ca);
+    let mut connector = unreported_connector(&ca);
+    connector.pin("localhost", &[spki_hash(&ca)]);
+    let err = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err();
+    let err = support::connect_error(err);
+    assert!(
+        err.to_string()
+            .contains("only counts if the backend reports it"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn malformed_certificates_are_rejected() {
+    let der = Identity::ca().issue(&["localhost"]).cert.to_der().unwrap();
+    assert!(SpkiHash::of_certificate(&der).is_ok());
+    assert!(PeerCertificate::from_der(&der).is_ok());
+
+    for len in 0..der.len() {
+        assert!(SpkiHash::of_certificate(&der[..len]).is_err());
+        assert!(PeerCertificate::from_der(&der[..len]).is_err());
+    }
+    let mut trailing = der.clone();
+    trailing.push(0);
+    assert!(SpkiHash::of_certificate(&trailing).is_err());
+
+    // Corrupting any byte must not panic, whether or not it still parses.
+    for i in 0..der.len() {
+        for &mask in &[0x01, 0x7f, 0x80, 0xff] {
+            let mut corrupt = der.clone();
+            corrupt[i] ^= mask;
+            let _ = SpkiHash::of_certificate(&corrupt);
+            let _ = PeerCertificate::from_der(&corrupt);
+        }
+    }
+}
+
+#[test]
+fn base64_round_trip() {
+    let hash = SpkiHash([7; 32]);
+    let encoded = hash.to_string();
+    assert_eq!(SpkiHash::from_base64(&encoded).unwrap(), hash);
+    assert!(SpkiHash::from_base64("not a pin").is_err());
+    assert!(SpkiHash::from_base64("AAAA").is_err());
+}
@@ -0,0 +1,233 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use support::Identity;
+
+fn proxy_uri(proxy: &support::Proxy) -> Option<hyper::Uri> {
+    Some(format!("http://{}", proxy.addr).parse().unwrap())
+}
+
+#[test]
+fn https_is_tunnelled_with_connect() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::proxy_server(1);
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_proxy(proxy_uri(&proxy)).unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+
+    let heads = proxy.heads();
+    assert_eq!(heads.len(), 1);
+    let expected = format!("CONNECT localhost:{} HTTP/1.1\r\n", server.port());
+    assert!(heads[0].starts_with(&expected), "{:?}", heads[0]);
+}
+
+#[test]
+fn http_is_sent_in_absolute_form() {
+    let ca = Identity::ca();
+    let server = support::http_server(1);
+    let proxy = support::proxy_server(1);
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_proxy(proxy_uri(&proxy)).unwrap();
+    let uri = format!("http://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+
+    let heads = proxy.heads();
+    let expected = format!("GET http://localhost:{}/ HTTP/1.1\r\n", server.port());
+    assert!(heads[0].starts_with(&expected), "{:?}", heads[0]);
+}
+
+#[test]
+fn refused_tunnel_is_an_error() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::proxy_server_with(1, |_| Some("HTTP/1.1 403 Forbidden"));
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_proxy(proxy_uri(&proxy)).unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::get(connector, &uri).unwrap_err();
+    let cause = err.into_cause().expect("connect error has a cause");
+    assert!(cause.to_string().contains("403 Forbidden"), "{}", cause);
+}
+
+#[test]
+fn tunnelled_certificate_is_still_verified() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::proxy_server(1);
+
+    let mut connector = support::https_connector(&Identity::ca());
+    connector.set_proxy(proxy_uri(&proxy)).unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    assert!(support::get(connector, &uri).is_err());
+}
+
+#[test]
+fn proxy_must_be_an_http_uri() {
+    let ca = Identity::ca();
+    let mut connector = support::https_connector(&ca);
+    assert!(connector
+        .set_proxy(Some("socks5://localhost:1080".parse().unwrap()))
+        .is_err());
+    assert!(connector
+        .set_proxy(Some("/relative".parse().unwrap()))
+        .is_err());
+    assert!(connector.set_proxy(None).is_ok());
+}
+
+#[test]
+fn userinfo_is_sent_as_basic_credentials() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::proxy_server(1);
+
+    let mut connector = support::https_connector(&ca);
+    let uri = format!("http://alice:p%40ss@{}", proxy.addr);
+    connector.set_proxy(Some(uri.parse().unwrap())).unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+
+    // base64("alice:p@ss")
+    assert!(proxy.heads()[0].contains("\r\nProxy-Authorization: Basic YWxpY2U6cEBzcw==\r\n"));
+}
+
+#[test]
+fn missing_credentials_are_refused() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::proxy_server_with(1, |head| {
+        if head.contains("Proxy-Authorization:") {
+            None
+        } else {
+            Some("HTTP/1.1 407 Proxy Authentication Required")
+        }
+    });
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_proxy(proxy_uri(&proxy)).unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::get(connector, &uri).unwrap_err();
+    let cause = err.into_cause().expect("connect error has a cause");
+    assert!(cause.to_string().contains("407"), "{}", cause);
+}
+
+#[test]
+fn no_proxy_hosts_are_connected_directly() {
+    let ca = Identity::ca();
+    let proxy = support::proxy_server(1);
+
+    for rules in &["localhost", ".localhost", "example.com, *", "127.0.0.0/8"] {
+        let server = support::https_server(&ca.issue(&["localhost", "127.0.0.1"]), 2);
+        let mut connector = support::https_connector(&ca);
+        connector.set_proxy(proxy_uri(&proxy)).unwrap();
+        connector.set_no_proxy(rules);
+        let uri = format!("https://localhost:{}/", server.port());
+        let by_ip = format!("https://127.0.0.1:{}/", server.port());
+        let direct = if rules.contains('/') { &by_ip } else { &uri };
+        assert_eq!(support::get(connector, direct).unwrap(), support::BODY);
+    }
+    assert!(proxy.heads().is_empty());
+}
+
+#[test]
+fn no_proxy_does_not_match_other_hosts() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::proxy_server(1);
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_proxy(proxy_uri(&proxy)).unwrap();
+    connector.set_no_proxy("host, calhost, 10.0.0.0/8");
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert_eq!(proxy.heads().len(), 1);
+}
+
+#[test]
+fn https_proxy_nests_tls_sessions() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::https_proxy_server(&ca.issue(&["localhost"]), 1);
+
+    let mut connector = support::https_connector(&ca);
+    let proxy_uri = format!("https://localhost:{}", proxy.addr.port());
+    connector
+        .set_proxy(Some(proxy_uri.parse().unwrap()))
+        .unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+
+    let expected = format!("CONNECT localhost:{} HTTP/1.1\r\n", server.port());
+    assert!(
+        proxy.heads()[0].starts_with(&expected),
+        "{:?}",
+        proxy.heads()
+    );
+}
+
+#[test]
+fn https_proxy_carries_plain_http() {
+    let ca = Identity::ca();
+    let server = support::http_server(1);
+    let proxy = support::https_proxy_server(&ca.issue(&["localhost"]), 1);
+
+    let mut connector = support::https_connector(&ca);
+    let proxy_uri = format!("https://localhost:{}", proxy.addr.port());
+    connector
+        .set_proxy(Some(proxy_uri.parse().unwrap()))
+        .unwrap();
+    let uri = format!("http://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+
+    let expected = format!("GET http://localhost:{}/ HTTP/1.1\r\n", server.port());
+    assert!(
+        proxy.heads()[0].starts_with(&expected),
+        "{:?}",
+        proxy.heads()
+    );
+}
+
+#[test]
+fn https_proxy_certificate_is_verified() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::https_proxy_server(&Identity::ca().issue(&["localhost"]), 1);
+
+    let mut connector = support::https_connector(&ca);
+    let proxy_uri = format!("https://localhost:{}", proxy.addr.port());
+    connector
+        .set_proxy(Some(proxy_uri.parse().unwrap()))
+        .unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    assert!(support::get(connector, &uri).is_err());
+    assert!(proxy.heads().is_empty());
+}
+
+#[test]
+fn https_proxy_is_not_offered_h2() {
+    let ca = Identity::ca();
+    let server = support::https_server_with(
+        support::acceptor_with_alpn(&ca.issue(&["localhost"]), &[b"http/1.1"]),
+        1,
+    );
+    let proxy = support::https_proxy_server(&ca.issue(&["localhost"]), 1);
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_alpn_protocols(&["h2", "http/1.1"]).unwrap();
+    let proxy_uri = format!("https://localhost:{}", proxy.addr.port());
+    connector
+        .set_proxy(Some(proxy_uri.parse().unwrap()))
+        .unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+}
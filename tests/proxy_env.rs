@@ -0,0 +1,208 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::connect::Destination;
+use hyper::client::HttpConnector;
+use hyper::header::PROXY_AUTHORIZATION;
+use hyper::{Body, Client, Request, StatusCode};
+use hyper_tls_api::HttpsConnector;
+use std::env;
+use std::sync::{Mutex, MutexGuard};
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+const VARS: &[&str] = &[
+    "http_proxy",
+    "HTTP_PROXY",
+    "https_proxy",
+    "HTTPS_PROXY",
+    "all_proxy",
+    "ALL_PROXY",
+    "no_proxy",
+    "NO_PROXY",
+];
+
+/// Sets the proxy variables to `vars` alone. The environment is shared by
+/// the whole process, so the guard keeps other tests from touching it.
+fn set_env(vars: &[(&str, String)]) -> MutexGuard<'static, ()> {
+    static ENV: Mutex<()> = Mutex::new(());
+    let guard = ENV.lock().unwrap_or_else(|e| e.into_inner());
+    for var in VARS {
+        env::remove_var(var);
+    }
+    for &(name, ref value) in vars {
+        env::set_var(name, value);
+    }
+    guard
+}
+
+fn env_connector(ca: &Identity) -> HttpsConnector<HttpConnector, support::Connector> {
+    let mut connector = support::https_connector(ca);
+    connector.set_proxies_from_env().unwrap();
+    connector
+}
+
+#[test]
+fn https_proxy_tunnels_https() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::proxy_server(1);
+    // A proxy without a scheme is an HTTP proxy.
+    let _env = set_env(&[("HTTPS_PROXY", proxy.addr.to_string())]);
+
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(
+        support::get(env_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+    let expected = format!("CONNECT localhost:{} HTTP/1.1\r\n", server.port());
+    assert!(
+        proxy.heads()[0].starts_with(&expected),
+        "{:?}",
+        proxy.heads()
+    );
+}
+
+#[test]
+fn lower_case_wins_and_cgi_http_proxy_is_ignored() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let plain = support::http_server(1);
+    let lower = support::proxy_server(1);
+    let upper = support::proxy_server(1);
+    let _env = set_env(&[
+        ("https_proxy", format!("http://{}", lower.addr)),
+        ("HTTPS_PROXY", format!("http://{}", upper.addr)),
+        ("HTTP_PROXY", format!("http://{}", upper.addr)),
+    ]);
+
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(
+        support::get(env_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+    let uri = format!("http://localhost:{}/", plain.port());
+    let connector = HttpsConnector::<_, support::Connector>::from_env(1).unwrap();
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+
+    assert_eq!(lower.heads().len(), 1);
+    assert!(upper.heads().is_empty(), "{:?}", upper.heads());
+}
+
+#[test]
+fn all_proxy_is_the_fallback() {
+    let plain = support::http_server(1);
+    let proxy = support::proxy_server(1);
+    let _env = set_env(&[("ALL_PROXY", format!("http://{}", proxy.addr))]);
+
+    let uri = format!("http://localhost:{}/", plain.port());
+    let connector = HttpsConnector::<_, support::Connector>::from_env(1).unwrap();
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    let expected = format!("GET {} HTTP/1.1\r\n", uri);
+    assert!(
+        proxy.heads()[0].starts_with(&expected),
+        "{:?}",
+        proxy.heads()
+    );
+}
+
+#[test]
+fn no_proxy_hosts_are_connected_directly() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 3);
+    let proxy = support::proxy_server(1);
+    let uri = format!("https://localhost:{}/", server.port());
+
+    for rules in &[
+        "example.com,localhost".to_owned(),
+        format!("localhost:{}", server.port()),
+    ] {
+        let _env = set_env(&[
+            ("https_proxy", format!("http://{}", proxy.addr)),
+            ("NO_PROXY", rules.clone()),
+        ]);
+        assert_eq!(
+            support::get(env_connector(&ca), &uri).unwrap(),
+            support::BODY
+        );
+    }
+    assert!(proxy.heads().is_empty(), "{:?}", proxy.heads());
+
+    // A rule for another port does not match.
+    let _env = set_env(&[
+        ("https_proxy", format!("http://{}", proxy.addr)),
+        ("no_proxy", format!("localhost:{}", server.port() + 1)),
+    ]);
+    assert_eq!(
+        support::get(env_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+    assert_eq!(proxy.heads().len(), 1);
+}
+
+#[test]
+fn credentials_are_given_for_plain_http_requests() {
+    let plain = support::http_server(1);
+    let proxy = support::proxy_server_with(1, |head| {
+        if head
+            .to_ascii_lowercase()
+            .contains("\r\nproxy-authorization: basic dxnlcjpwyxnz\r\n")
+        {
+            None
+        } else {
+            Some("HTTP/1.1 407 Proxy Authentication Required")
+        }
+    });
+    let _env = set_env(&[("http_proxy", format!("http://user:pass@{}", proxy.addr))]);
+    let connector = HttpsConnector::<_, support::Connector>::from_env(1).unwrap();
+
+    let uri: hyper::Uri = format!("http://localhost:{}/", plain.port())
+        .parse()
+        .unwrap();
+    let dst = Destination::try_from_uri(uri.clone()).unwrap();
+    let authorization = connector.proxy_authorization(&dst).unwrap();
+    assert_eq!(authorization, "Basic dXNlcjpwYXNz");
+    // The tunnel carries them for HTTPS destinations.
+    let https = Destination::try_from_uri("https://localhost/".parse().unwrap()).unwrap();
+    assert_eq!(connector.proxy_authorization(&https), None);
+
+    let mut req = Request::get(uri).body(Body::empty()).unwrap();
+    req.headers_mut().insert(PROXY_AUTHORIZATION, authorization);
+    let client = Client::builder().build::<_, Body>(connector);
+    let res = Runtime::new()
+        .unwrap()
+        .block_on(client.request(req))
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(proxy.heads().len(), 1);
+}
+
+#[test]
+fn socks_proxies_are_skipped() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let _env = set_env(&[
+        ("ALL_PROXY", "socks5://127.0.0.1:1".to_owned()),
+        ("https_proxy", "socks5h://127.0.0.1:1".to_owned()),
+    ]);
+
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(
+        support::get(env_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn invalid_proxies_are_reported() {
+    let _env = set_env(&[("https_proxy", "http://proxy example:3128".to_owned())]);
+    let err = HttpsConnector::<_, support::Connector>::from_env(1).unwrap_err();
+    assert!(err.to_string().contains("https_proxy"), "{}", err);
+}
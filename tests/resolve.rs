@@ -0,0 +1,64 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::{HttpsConnector, StaticResolver};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use tls_api::TlsConnectorBuilder;
+
+use support::Identity;
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+#[test]
+fn static_resolver_routes_http_requests() {
+    let addr = support::http_server(1);
+    let mut resolver = StaticResolver::new();
+    resolver.insert("backend.test", LOCALHOST);
+    let connector = HttpsConnector::<_, support::Connector>::with_resolver(resolver).unwrap();
+
+    let body = support::get(connector, &format!("http://backend.test:{}/", addr.port())).unwrap();
+    assert_eq!(body, support::BODY);
+}
+
+#[test]
+fn static_resolver_keeps_the_name_for_verification() {
+    let ca = Identity::ca();
+    let addr = support::https_server(&ca.issue(&["backend.test"]), 1);
+    let mut resolver = StaticResolver::new();
+    resolver.insert("Backend.Test", LOCALHOST);
+    let mut http = HttpConnector::new_with_resolver(resolver);
+    http.enforce_http(false);
+    let der = ca.cert.to_der().unwrap();
+    let connector = HttpsConnector::<_, support::Connector>::with_tls_builder(
+        http,
+        move |builder: &mut support::ConnectorBuilder| {
+            builder.add_root_certificate(tls_api::Certificate::from_der(der.clone()))?;
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    let body = support::get(connector, &format!("https://backend.test:{}/", addr.port())).unwrap();
+    assert_eq!(body, support::BODY);
+}
+
+#[test]
+fn unknown_names_are_not_found() {
+    let connector =
+        HttpsConnector::<_, support::Connector>::with_resolver(StaticResolver::new()).unwrap();
+
+    let err = support::get(connector, "http://missing.test/").unwrap_err();
+    let cause = err.into_cause().expect("connect error has a cause");
+    let io = cause
+        .downcast_ref::<io::Error>()
+        .expect("connect error is an io::Error");
+    assert_eq!(io.kind(), io::ErrorKind::NotFound);
+}
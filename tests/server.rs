@@ -0,0 +1,139 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use futures::{Future, Stream};
+use hyper::service::service_fn_ok;
+use hyper::{Body, Response, Server};
+use hyper_tls_api::HttpsAcceptor;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+fn acceptor(identity: &Identity) -> HttpsAcceptor<support::Acceptor> {
+    HttpsAcceptor::from(support::acceptor(identity))
+}
+
+fn serve(rt: &mut Runtime, acceptor: HttpsAcceptor<support::Acceptor>) -> SocketAddr {
+    let incoming = acceptor.bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = incoming.local_addr().unwrap();
+
+    let server = Server::builder(incoming)
+        .serve(|| service_fn_ok(|_| Response::new(Body::from(support::BODY))))
+        .map_err(|e| panic!("server failed: {}", e));
+    rt.spawn(server);
+    addr
+}
+
+#[test]
+fn serves_https() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let addr = serve(&mut rt, acceptor(&ca.issue(&["localhost"])));
+    let uri = format!("https://localhost:{}/", addr.port());
+
+    assert_eq!(
+        support::get_on(&mut rt, support::https_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+    assert_eq!(
+        support::get_on(&mut rt, support::https_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn pending_handshake_does_not_block_other_connections() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let addr = serve(&mut rt, acceptor(&ca.issue(&["localhost"])));
+    let uri = format!("https://localhost:{}/", addr.port());
+
+    // Opens a connection and sends half a record, leaving its handshake hanging.
+    let mut stalled = TcpStream::connect(addr).unwrap();
+    stalled.write_all(&[0x16, 0x03, 0x01]).unwrap();
+
+    assert_eq!(
+        support::get_on(&mut rt, support::https_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn failed_handshake_does_not_end_the_server() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let addr = serve(&mut rt, acceptor(&ca.issue(&["localhost"])));
+    let uri = format!("https://localhost:{}/", addr.port());
+
+    let mut garbage = TcpStream::connect(addr).unwrap();
+    garbage.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut buf = Vec::new();
+    let _ = garbage.read_to_end(&mut buf);
+
+    assert_eq!(
+        support::get_on(&mut rt, support::https_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn stalled_handshake_is_dropped_after_the_deadline() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let mut acceptor = acceptor(&ca.issue(&["localhost"]));
+    acceptor.set_max_handshakes(1);
+    acceptor.set_handshake_timeout(Some(Duration::from_millis(200)));
+    let addr = serve(&mut rt, acceptor);
+    let uri = format!("https://localhost:{}/", addr.port());
+
+    let start = Instant::now();
+    let mut stalled = TcpStream::connect(addr).unwrap();
+    stalled.write_all(&[0x16, 0x03, 0x01]).unwrap();
+
+    // The stalled client holds the only handshake slot until it times out.
+    assert_eq!(
+        support::get_on(&mut rt, support::https_connector(&ca), &uri).unwrap(),
+        support::BODY
+    );
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    let mut buf = [0; 1];
+    assert_eq!(stalled.read(&mut buf).unwrap_or(0), 0);
+}
+
+#[test]
+fn timer_failure_drops_only_that_connection() {
+    let ca = Identity::ca();
+    let incoming = acceptor(&ca.issue(&["localhost"]))
+        .bind(&"127.0.0.1:0".parse().unwrap())
+        .unwrap();
+    let addr = incoming.local_addr().unwrap();
+
+    // Without a tokio runtime there is no timer, so every handshake deadline
+    // fails.
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for accepted in incoming.wait() {
+            tx.send(accepted.map(|_| ())).unwrap();
+        }
+    });
+
+    for _ in 0..2 {
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled.write_all(&[0x16, 0x03, 0x01]).unwrap();
+        let mut buf = [0; 1];
+        assert_eq!(stalled.read(&mut buf).unwrap_or(0), 0);
+    }
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+}
@@ -0,0 +1,333 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate serde;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use futures::Future;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Response, Server};
+use hyper_tls_api::{
+    ClientAuth, HttpsAcceptor, HttpsConnector, IdentityConfig, ServerTlsHooks, TlsClientConfig,
+    TlsServerConfig,
+};
+use serde::de::value::{self, MapDeserializer, StrDeserializer};
+use serde::Deserialize;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+use support::{Identity, TempDir};
+
+fn identity_config(dir: &TempDir, identity: &Identity) -> IdentityConfig {
+    IdentityConfig::Pem {
+        certificate: dir.file(&identity.cert.to_pem().unwrap()),
+        private_key: dir.file(&identity.key.private_key_to_pem_pkcs8().unwrap()),
+    }
+}
+
+fn config(dir: &TempDir, identity: &Identity) -> TlsServerConfig {
+    match identity_config(dir, identity) {
+        IdentityConfig::Pem {
+            certificate,
+            private_key,
+        } => TlsServerConfig::new(certificate, private_key),
+        IdentityConfig::Pkcs12 { .. } => unreachable!(),
+    }
+}
+
+fn serve(rt: &mut Runtime, config: &TlsServerConfig) -> SocketAddr {
+    let acceptor =
+        HttpsAcceptor::<support::Acceptor>::from_config(config, support::server_hooks()).unwrap();
+    let incoming = acceptor.bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = incoming.local_addr().unwrap();
+
+    let server = Server::builder(incoming)
+        .serve(|| service_fn_ok(|_| Response::new(Body::from(support::BODY))))
+        .map_err(|e| panic!("server failed: {}", e));
+    rt.spawn(server);
+    addr
+}
+
+fn invalid(config: &TlsServerConfig) -> io::Error {
+    let err = config.validate().unwrap_err();
+    let built = config
+        .acceptor::<support::Acceptor>(support::server_hooks())
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(built.to_string(), err.to_string());
+    err
+}
+
+#[test]
+fn serves_https_from_config() {
+    let dir = TempDir::new();
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let mut config = config(&dir, &ca.issue(&["localhost"]));
+    config.alpn_protocols = vec!["http/1.1".to_owned()];
+    let addr = serve(&mut rt, &config);
+
+    assert_eq!(
+        support::get_on(
+            &mut rt,
+            support::https_connector(&ca),
+            &format!("https://localhost:{}/", addr.port()),
+        )
+        .unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn sni_selects_certificate() {
+    let dir = TempDir::new();
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let mut config = config(&dir, &ca.issue(&["default.test"]));
+    config.sni.insert(
+        "localhost".to_owned(),
+        identity_config(&dir, &ca.issue(&["localhost"])),
+    );
+    let addr = serve(&mut rt, &config);
+
+    assert_eq!(
+        support::get_on(
+            &mut rt,
+            support::https_connector(&ca),
+            &format!("https://localhost:{}/", addr.port()),
+        )
+        .unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn required_client_auth() {
+    let dir = TempDir::new();
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let mut config = config(&dir, &ca.issue(&["localhost"]));
+    config.client_auth = ClientAuth::Required;
+    config.client_ca_files = vec![dir.file(&ca.cert.to_pem().unwrap())];
+    let addr = serve(&mut rt, &config);
+
+    assert!(support::get_on(
+        &mut rt,
+        support::https_connector(&ca),
+        &format!("https://localhost:{}/", addr.port()),
+    )
+    .is_err());
+
+    let client = TlsClientConfig {
+        ca_files: vec![dir.file(&ca.cert.to_pem().unwrap())],
+        system_roots: false,
+        identity: Some(identity_config(&dir, &ca.issue(&["client"]))),
+        ..TlsClientConfig::default()
+    };
+    let connector =
+        HttpsConnector::<_, support::Connector>::from_config(&client, support::client_hooks())
+            .unwrap();
+    assert_eq!(
+        support::get_on(
+            &mut rt,
+            connector,
+            &format!("https://localhost:{}/", addr.port())
+        )
+        .unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn mismatched_key_is_reported() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let mut config = config(&dir, &ca.issue(&["localhost"]));
+    config.validate().unwrap();
+
+    let example = ca.issue(&["example.com"]);
+    let other = ca.issue(&["example.com"]);
+    config.sni.insert(
+        "www.example.com".to_owned(),
+        IdentityConfig::Pem {
+            certificate: dir.file(&example.cert.to_pem().unwrap()),
+            private_key: dir.file(&other.key.private_key_to_pem_pkcs8().unwrap()),
+        },
+    );
+    let err = invalid(&config);
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.to_string(),
+        "sni.\"www.example.com\": key does not match certificate for example.com"
+    );
+
+    // SEC 1 keys are checked too.
+    let sec1 = other.key.ec_key().unwrap().private_key_to_pem().unwrap();
+    let config = TlsServerConfig::new(dir.file(&example.cert.to_pem().unwrap()), dir.file(&sec1));
+    assert_eq!(
+        invalid(&config).to_string(),
+        "identity: key does not match certificate for example.com"
+    );
+
+    // Without a common name, the certificate goes by its first DNS name.
+    let unnamed = ca.issue_without_subject(&["127.0.0.1", "api.example.com", "example.com"]);
+    let config = TlsServerConfig::new(
+        dir.file(&unnamed.cert.to_pem().unwrap()),
+        dir.file(&other.key.private_key_to_pem_pkcs8().unwrap()),
+    );
+    assert_eq!(
+        invalid(&config).to_string(),
+        "identity: key does not match certificate for api.example.com"
+    );
+}
+
+#[test]
+fn rsa_keys_are_checked() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let leaf = ca.issue_rsa(&["localhost"]);
+    let other = ca.issue_rsa(&["localhost"]);
+    let certificate = dir.file(&leaf.cert.to_pem().unwrap());
+    let pkcs1 = |identity: &Identity| identity.key.rsa().unwrap().private_key_to_pem().unwrap();
+
+    TlsServerConfig::new(certificate.clone(), dir.file(&pkcs1(&leaf)))
+        .validate()
+        .unwrap();
+    TlsServerConfig::new(
+        certificate.clone(),
+        dir.file(&leaf.key.private_key_to_pem_pkcs8().unwrap()),
+    )
+    .validate()
+    .unwrap();
+
+    let config = TlsServerConfig::new(certificate, dir.file(&pkcs1(&other)));
+    assert_eq!(
+        invalid(&config).to_string(),
+        "identity: key does not match certificate for localhost"
+    );
+}
+
+#[test]
+fn invalid_settings_are_reported() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let valid = config(&dir, &ca.issue(&["localhost"]));
+
+    let mut config = valid.clone();
+    config.client_auth = ClientAuth::Required;
+    assert_eq!(
+        invalid(&config).to_string(),
+        "client_auth needs at least one of client_ca_files"
+    );
+
+    let mut config = valid.clone();
+    config.max_handshakes = 0;
+    assert_eq!(
+        invalid(&config).to_string(),
+        "max_handshakes must be at least 1"
+    );
+
+    let mut config = valid.clone();
+    config.sni.insert(
+        "example.com:443".to_owned(),
+        identity_config(&dir, &ca.issue(&["x"])),
+    );
+    assert_eq!(
+        invalid(&config).to_string(),
+        "invalid SNI server name \"example.com:443\""
+    );
+
+    let mut config = valid.clone();
+    config.client_auth = ClientAuth::Optional;
+    config.client_ca_files = vec![PathBuf::from("/nonexistent/clients.pem")];
+    let err = invalid(&config);
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(
+        err.to_string().contains("/nonexistent/clients.pem"),
+        "{}",
+        err
+    );
+
+    let config = TlsServerConfig::new(
+        PathBuf::from("/nonexistent/server.pem"),
+        PathBuf::from("/nonexistent/server.key"),
+    );
+    let err = invalid(&config);
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(
+        err.to_string()
+            .starts_with("identity: /nonexistent/server.pem"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn deserializing() {
+    let mode = ClientAuth::deserialize(StrDeserializer::<value::Error>::new("required"));
+    assert_eq!(mode.unwrap(), ClientAuth::Required);
+
+    let fields = vec![("client_cas", "ca.pem")];
+    let de = MapDeserializer::<_, value::Error>::new(fields.into_iter());
+    let err = TlsServerConfig::deserialize(de).unwrap_err();
+    assert!(err.to_string().contains("client_cas"), "{}", err);
+
+    let fields: Vec<(&str, &str)> = Vec::new();
+    let de = MapDeserializer::<_, value::Error>::new(fields.into_iter());
+    let err = TlsServerConfig::deserialize(de).unwrap_err();
+    assert!(err.to_string().contains("identity"), "{}", err);
+}
+
+#[test]
+fn handshake_limits_are_applied() {
+    let dir = TempDir::new();
+    let mut config = config(&dir, &Identity::ca().issue(&["localhost"]));
+    config.max_handshakes = 4;
+    config.handshake_timeout_ms = 0;
+    let acceptor =
+        HttpsAcceptor::<support::Acceptor>::from_config(&config, support::server_hooks()).unwrap();
+    assert_eq!(
+        format!("{:?}", acceptor),
+        "HttpsAcceptor { max_handshakes: 4, handshake_timeout: None }"
+    );
+}
+
+#[test]
+fn missing_hooks_are_named() {
+    let dir = TempDir::new();
+    let ca = Identity::ca();
+    let hooks = || ServerTlsHooks::new(support::acceptor_builder);
+
+    let mut config = config(&dir, &ca.issue(&["localhost"]));
+    config.sni.insert(
+        "example.com".to_owned(),
+        identity_config(&dir, &ca.issue(&["example.com"])),
+    );
+    let err = config
+        .acceptor::<support::Acceptor>(hooks())
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "the TLS backend needs an on_sni_identity hook to choose a certificate by SNI"
+    );
+
+    let mut config = config.clone();
+    config.sni.clear();
+    config.client_auth = ClientAuth::Optional;
+    config.client_ca_files = vec![dir.file(&ca.cert.to_pem().unwrap())];
+    let err = config
+        .acceptor::<support::Acceptor>(hooks())
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "the TLS backend needs an on_client_auth hook to verify client certificates"
+    );
+}
@@ -0,0 +1,79 @@
+//! Runs alone in its own process, since it lowers the limit on open file
+//! descriptors for the whole process.
+#![cfg(target_os = "linux")]
+
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate libc;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use futures::Future;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Response, Server};
+use hyper_tls_api::HttpsAcceptor;
+use std::fs;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+fn open_file_limit() -> libc::rlimit {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    assert_eq!(
+        unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) },
+        0
+    );
+    limit
+}
+
+fn set_open_file_limit(limit: &libc::rlimit) {
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, limit) }, 0);
+}
+
+#[test]
+fn running_out_of_file_descriptors_does_not_end_the_server() {
+    let mut rt = Runtime::new().unwrap();
+    let ca = Identity::ca();
+    let incoming = HttpsAcceptor::from(support::acceptor(&ca.issue(&["localhost"])))
+        .bind(&"127.0.0.1:0".parse().unwrap())
+        .unwrap();
+    let addr = incoming.local_addr().unwrap();
+    let server = Server::builder(incoming)
+        .serve(|| service_fn_ok(|_| Response::new(Body::from(support::BODY))))
+        .map_err(|e| eprintln!("server failed: {}", e));
+    rt.spawn(server);
+
+    let original = open_file_limit();
+    let open = fs::read_dir("/proc/self/fd").unwrap().count() as libc::rlim_t;
+    set_open_file_limit(&libc::rlimit {
+        rlim_cur: open + 8,
+        ..original
+    });
+
+    // Connect until no descriptors are left, then trade one client for a
+    // connection that the server cannot accept.
+    let mut clients = Vec::new();
+    while let Ok(client) = TcpStream::connect(addr) {
+        clients.push(client);
+    }
+    clients.pop();
+    clients.push(TcpStream::connect(addr).unwrap());
+    thread::sleep(Duration::from_millis(200));
+
+    drop(clients);
+    set_open_file_limit(&original);
+
+    let uri = format!("https://localhost:{}/", addr.port());
+    let body = support::get_on(&mut rt, support::https_connector(&ca), &uri).unwrap();
+    assert_eq!(body, support::BODY);
+}
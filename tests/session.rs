@@ -0,0 +1,204 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use futures::{Future, Stream};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Response};
+use hyper_tls_api::{
+    accept_async, connect_async, HttpsConnector, SessionInfo, TlsInfo, TlsVersion,
+};
+use openssl::ssl::{SslConnector, SslMethod, SslVersion};
+use std::io;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tls_api::{TlsAcceptorBuilder, TlsConnector, TlsConnectorBuilder};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+
+use support::Identity;
+
+fn server(identity: &Identity, max_version: Option<SslVersion>) -> SocketAddr {
+    let mut builder = support::AcceptorBuilder::new(identity).unwrap();
+    builder.set_alpn_protocols(&[b"http/1.1"]).unwrap();
+    builder
+        .underlying_mut()
+        .set_max_proto_version(max_version)
+        .unwrap();
+    support::https_server_with(builder.build().unwrap(), 1)
+}
+
+fn client_session(ca: &Identity, addr: SocketAddr, report: bool) -> SessionInfo {
+    let mut builder = support::Connector::builder().unwrap();
+    if report {
+        support::report_verification(&mut builder);
+    }
+    builder
+        .add_root_certificate(tls_api::Certificate::from_der(ca.cert.to_der().unwrap()))
+        .unwrap();
+    builder.set_alpn_protocols(&[b"http/1.1"]).unwrap();
+    let connector = builder.build().unwrap();
+
+    let mut rt = Runtime::new().unwrap();
+    let stream = rt
+        .block_on(TcpStream::connect(&addr).and_then(move |tcp| {
+            connect_async(&connector, "localhost", tcp).map_err(io::Error::from)
+        }))
+        .unwrap();
+    stream.session_info().clone()
+}
+
+fn request(ca: &Identity, addr: SocketAddr, report: bool) -> Response<Body> {
+    let ca = ca.cert.to_der().unwrap();
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    let connector: HttpsConnector<_, support::Connector> =
+        HttpsConnector::with_tls_builder(http, move |builder: &mut support::ConnectorBuilder| {
+            builder.add_root_certificate(tls_api::Certificate::from_der(ca.clone()))?;
+            if report {
+                support::report_verification(builder);
+            }
+            Ok(())
+        })
+        .unwrap();
+    let client = Client::builder().build::<_, Body>(connector);
+    let uri = format!("https://localhost:{}/", addr.port());
+    Runtime::new()
+        .unwrap()
+        .block_on(client.get(uri.parse().unwrap()))
+        .unwrap()
+}
+
+#[test]
+fn tls12_session_includes_peer_certificates() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, Some(SslVersion::TLS1_2));
+
+    let info = client_session(&ca, addr, false);
+    assert_eq!(info.version(), Some(TlsVersion::Tls12));
+    assert!(info.cipher_suite().is_some());
+    assert_eq!(info.alpn_protocol(), Some(&b"http/1.1"[..]));
+    assert_eq!(info.server_name(), Some("localhost"));
+    let chain = info.peer_certificates().expect("peer certificates");
+    assert_eq!(chain[0], leaf.cert.to_der().unwrap());
+    assert!(!info.peer_certificates_reported());
+}
+
+#[test]
+fn tls13_session_has_no_peer_certificates() {
+    let ca = Identity::ca();
+    let addr = server(&ca.issue(&["localhost"]), None);
+
+    let info = client_session(&ca, addr, false);
+    assert_eq!(info.version(), Some(TlsVersion::Tls13));
+    let suite = info.cipher_suite().expect("cipher suite");
+    assert!(suite.name().unwrap().starts_with("TLS_AES_"));
+    assert_eq!(info.alpn_protocol(), Some(&b"http/1.1"[..]));
+    assert_eq!(info.server_name(), Some("localhost"));
+    assert_eq!(info.peer_certificates(), None);
+}
+
+#[test]
+fn tls13_session_includes_reported_peer_certificates() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, None);
+
+    let info = client_session(&ca, addr, true);
+    assert_eq!(info.version(), Some(TlsVersion::Tls13));
+    let chain = info.peer_certificates().expect("peer certificates");
+    assert_eq!(chain[0], leaf.cert.to_der().unwrap());
+    assert_eq!(chain[1], ca.cert.to_der().unwrap());
+    assert!(info.peer_certificates_reported());
+}
+
+#[test]
+fn server_sees_requested_server_name() {
+    let ca = Identity::ca();
+    let acceptor = support::acceptor(&ca.issue(&["localhost"]));
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let cert = ca.cert.clone();
+    let client = thread::spawn(move || {
+        let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
+        builder.cert_store_mut().add_cert(cert).unwrap();
+        let tcp = std::net::TcpStream::connect(addr).unwrap();
+        builder.build().connect("localhost", tcp).map(|_| ())
+    });
+
+    let mut rt = Runtime::new().unwrap();
+    let stream = rt
+        .block_on(listener.incoming().into_future().then(move |accepted| {
+            let tcp = accepted.ok().and_then(|(tcp, _)| tcp).unwrap();
+            accept_async(&acceptor, tcp).map_err(io::Error::from)
+        }))
+        .unwrap();
+    client.join().unwrap().unwrap();
+
+    let info = stream.session_info();
+    assert_eq!(info.server_name(), Some("localhost"));
+    assert_eq!(info.version(), Some(TlsVersion::Tls13));
+}
+
+#[test]
+fn responses_carry_the_peer_certificate() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, Some(SslVersion::TLS1_2));
+
+    let res = request(&ca, addr, false);
+    let tls = res
+        .extensions()
+        .get::<TlsInfo>()
+        .expect("TlsInfo extension");
+    assert_eq!(tls.version(), Some(TlsVersion::Tls12));
+
+    let cert = tls.peer_certificate().expect("peer certificate");
+    assert_eq!(cert.der(), &leaf.cert.to_der().unwrap()[..]);
+    assert_eq!(cert.subject(), "CN=localhost");
+    assert_eq!(cert.issuer(), "CN=hyper-tls-api test CA");
+
+    let now = SystemTime::now();
+    let day = Duration::from_secs(24 * 60 * 60);
+    assert!(cert.not_before() <= now);
+    assert!(cert.not_after() > now + 29 * day);
+    assert!(cert.not_after() < now + 31 * day);
+}
+
+#[test]
+fn tls13_responses_carry_the_version_only() {
+    let ca = Identity::ca();
+    let addr = server(&ca.issue(&["localhost"]), None);
+
+    let res = request(&ca, addr, false);
+    let tls = res
+        .extensions()
+        .get::<TlsInfo>()
+        .expect("TlsInfo extension");
+    assert_eq!(tls.version(), Some(TlsVersion::Tls13));
+    assert!(tls.peer_certificate().is_none());
+}
+
+#[test]
+fn tls13_responses_carry_the_reported_peer_certificate() {
+    let ca = Identity::ca();
+    let leaf = ca.issue(&["localhost"]);
+    let addr = server(&leaf, None);
+
+    let res = request(&ca, addr, true);
+    let tls = res
+        .extensions()
+        .get::<TlsInfo>()
+        .expect("TlsInfo extension");
+    assert_eq!(tls.version(), Some(TlsVersion::Tls13));
+    let cert = tls.peer_certificate().expect("peer certificate");
+    assert_eq!(cert.der(), &leaf.cert.to_der().unwrap()[..]);
+}
@@ -0,0 +1,187 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::{Error, HttpsConnector, SocksConnector, StaticResolver};
+use std::io;
+use tls_api::TlsConnectorBuilder;
+
+use support::Identity;
+
+fn connector(ca: &Identity, proxy: &str) -> HttpsConnector<SocksConnector, support::Connector> {
+    let socks = SocksConnector::new(proxy.parse().unwrap()).unwrap();
+    with_socks(ca, socks)
+}
+
+fn with_socks<R>(
+    ca: &Identity,
+    socks: SocksConnector<HttpConnector, R>,
+) -> HttpsConnector<SocksConnector<HttpConnector, R>, support::Connector> {
+    let ca = ca.cert.to_der().unwrap();
+    HttpsConnector::with_tls_builder(socks, move |builder: &mut support::ConnectorBuilder| {
+        builder.add_root_certificate(tls_api::Certificate::from_der(ca.clone()))?;
+        Ok(())
+    })
+    .unwrap()
+}
+
+fn http() -> HttpConnector {
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    http
+}
+
+#[test]
+fn socks5h_leaves_resolution_to_the_proxy() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::socks_server(1, None);
+
+    let connector = connector(&ca, &format!("socks5h://{}", proxy.addr));
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert_eq!(
+        proxy.targets(),
+        vec![format!("localhost:{}", server.port())]
+    );
+}
+
+#[test]
+fn socks5_resolves_locally() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::socks_server(1, None);
+
+    let connector = connector(&ca, &format!("socks5://{}", proxy.addr));
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert_eq!(
+        proxy.targets(),
+        vec![format!("127.0.0.1:{}", server.port())]
+    );
+}
+
+#[test]
+fn socks5_resolves_with_the_given_resolver() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["app.test"]), 1);
+    let proxy = support::socks_server(1, None);
+
+    let mut resolver = StaticResolver::new();
+    resolver.insert("app.test", "127.0.0.1".parse().unwrap());
+    let proxy_uri = format!("socks5://{}", proxy.addr).parse().unwrap();
+    let socks = SocksConnector::with_resolver(http(), proxy_uri, resolver).unwrap();
+    let uri = format!("https://app.test:{}/", server.port());
+    assert_eq!(
+        support::get(with_socks(&ca, socks), &uri).unwrap(),
+        support::BODY
+    );
+    assert_eq!(
+        proxy.targets(),
+        vec![format!("127.0.0.1:{}", server.port())]
+    );
+}
+
+#[test]
+fn socks5h_does_not_consult_the_resolver() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::socks_server(1, None);
+
+    // Knowing no names, the resolver would fail any lookup.
+    let proxy_uri = format!("socks5h://{}", proxy.addr).parse().unwrap();
+    let socks = SocksConnector::with_resolver(http(), proxy_uri, StaticResolver::new()).unwrap();
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(
+        support::get(with_socks(&ca, socks), &uri).unwrap(),
+        support::BODY
+    );
+}
+
+#[test]
+fn plain_http_is_carried_too() {
+    let ca = Identity::ca();
+    let server = support::http_server(1);
+    let proxy = support::socks_server(1, None);
+
+    let connector = connector(&ca, &format!("socks5h://{}", proxy.addr));
+    let uri = format!("http://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+}
+
+#[test]
+fn username_and_password_are_sent() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::socks_server(1, Some(("alice", "s3cr:t")));
+
+    let connector = connector(&ca, &format!("socks5h://alice:s3cr%3At@{}", proxy.addr));
+    let uri = format!("https://localhost:{}/", server.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+}
+
+#[test]
+fn wrong_password_is_rejected() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::socks_server(1, Some(("alice", "secret")));
+
+    let connector = connector(&ca, &format!("socks5h://alice:guess@{}", proxy.addr));
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::get(connector, &uri).unwrap_err();
+    let cause = err.into_cause().expect("connect error has a cause");
+    assert!(
+        cause.to_string().contains("authentication failed"),
+        "{}",
+        cause
+    );
+}
+
+#[test]
+fn missing_credentials_are_rejected() {
+    let ca = Identity::ca();
+    let server = support::https_server(&ca.issue(&["localhost"]), 1);
+    let proxy = support::socks_server(1, Some(("alice", "secret")));
+
+    let connector = connector(&ca, &format!("socks5h://{}", proxy.addr));
+    let uri = format!("https://localhost:{}/", server.port());
+    let err = support::get(connector, &uri).unwrap_err();
+    let cause = err.into_cause().expect("connect error has a cause");
+    assert!(cause.to_string().contains("no acceptable"), "{}", cause);
+}
+
+#[test]
+fn refused_destination_is_reported() {
+    let ca = Identity::ca();
+    let proxy = support::socks_server(1, None);
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = closed.local_addr().unwrap().port();
+    drop(closed);
+
+    let connector = connector(&ca, &format!("socks5h://{}", proxy.addr));
+    let err = support::get(connector, &format!("https://127.0.0.1:{}/", port)).unwrap_err();
+    let cause = err.into_cause().expect("connect error has a cause");
+    assert!(
+        cause.to_string().contains("connection refused"),
+        "{}",
+        cause
+    );
+    let io = cause.downcast_ref::<io::Error>().unwrap();
+    match Error::from_io_ref(io) {
+        Some(Error::Proxy(_)) => {}
+        other => panic!("expected a proxy error, got {:?}", other),
+    }
+}
+
+#[test]
+fn only_socks5_schemes_are_accepted() {
+    assert!(SocksConnector::new("socks4://localhost".parse().unwrap()).is_err());
+    assert!(SocksConnector::new("http://localhost".parse().unwrap()).is_err());
+    assert!(SocksConnector::new("socks5://localhost".parse().unwrap()).is_ok());
+}
@@ -0,0 +1,996 @@
+//! Shared fixtures for the integration tests.
+//!
+//! None of the published tls-api backends build against the OpenSSL found on
+//! current systems, so the tests carry a small OpenSSL adapter of their own.
+//! Certificates are generated on the fly for every test.
+#![allow(dead_code)]
+
+use std::cell::Cell;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::{Future, Stream as _};
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client};
+use hyper_tls_api::{ClientAuth, ClientTlsHooks, HttpsConnector, Phase, ServerTlsHooks, SpkiHash};
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::ssl::{
+    self, NameType, SniError, SslAcceptor, SslConnector, SslContext, SslContextBuilder, SslMethod,
+    SslVerifyMode,
+};
+use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{X509Builder, X509NameBuilder, X509};
+use tls_api::{self, Error, Result};
+use tokio::runtime::Runtime;
+
+pub struct ConnectorBuilder {
+    builder: ssl::SslConnectorBuilder,
+    verify_hostname: bool,
+    use_sni: bool,
+}
+
+pub struct Connector {
+    connector: SslConnector,
+    verify_hostname: bool,
+    use_sni: bool,
+}
+
+pub struct AcceptorBuilder {
+    builder: ssl::SslAcceptorBuilder,
+    sni: Vec<(String, SslContext)>,
+}
+
+pub struct Acceptor(SslAcceptor);
+
+#[derive(Debug)]
+struct Stream<S: Read + Write + fmt::Debug>(ssl::SslStream<S>);
+
+struct MidHandshake<S: Read + Write>(Option<ssl::MidHandshakeSslStream<S>>);
+
+impl<S: Read + Write> fmt::Debug for MidHandshake<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("MidHandshake")
+    }
+}
+
+impl<S: Read + Write + fmt::Debug> Read for Stream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<S: Read + Write + fmt::Debug> Write for Stream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S> tls_api::TlsStreamImpl<S> for Stream<S>
+where
+    S: Read + Write + fmt::Debug + Send + Sync + 'static,
+{
+    fn get_alpn_protocol(&self) -> Option<Vec<u8>> {
+        self.0.ssl().selected_alpn_protocol().map(Vec::from)
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        match self.0.shutdown() {
+            Ok(_) => Ok(()),
+            Err(ref e) if e.code() == ssl::ErrorCode::ZERO_RETURN => Ok(()),
+            Err(e) => Err(io::Error::other(e.to_string())),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut S {
+        self.0.get_mut()
+    }
+
+    fn get_ref(&self) -> &S {
+        self.0.get_ref()
+    }
+}
+
+impl<S> tls_api::MidHandshakeTlsStreamImpl<S> for MidHandshake<S>
+where
+    S: Read + Write + fmt::Debug + Send + Sync + 'static,
+{
+    fn handshake(&mut self) -> result::Result<tls_api::TlsStream<S>, tls_api::HandshakeError<S>> {
+        self.0
+            .take()
+            .expect("handshake resumed twice")
+            .handshake()
+            .map(|s| tls_api::TlsStream::new(Stream(s)))
+            .map_err(map_handshake_error)
+    }
+}
+
+fn map_handshake_error<S>(e: ssl::HandshakeError<S>) -> tls_api::HandshakeError<S>
+where
+    S: Read + Write + fmt::Debug + Send + Sync + 'static,
+{
+    match e {
+        ssl::HandshakeError::SetupFailure(e) => tls_api::HandshakeError::Failure(Error::new(e)),
+        ssl::HandshakeError::Failure(s) => {
+            tls_api::HandshakeError::Failure(Error::new(s.into_error()))
+        }
+        ssl::HandshakeError::WouldBlock(s) => tls_api::HandshakeError::Interrupted(
+            tls_api::MidHandshakeTlsStream::new(MidHandshake(Some(s))),
+        ),
+    }
+}
+
+fn encode_alpn_protocols(protocols: &[&[u8]]) -> Result<Vec<u8>> {
+    let mut wire = Vec::new();
+    for protocol in protocols {
+        if protocol.len() > 255 {
+            return Err(Error::new_other("ALPN protocol name too long"));
+        }
+        wire.push(protocol.len() as u8);
+        wire.extend_from_slice(protocol);
+    }
+    Ok(wire)
+}
+
+fn decode_certificate(cert: &tls_api::Certificate) -> Result<X509> {
+    match cert.format {
+        tls_api::CertificateFormat::DER => X509::from_der(&cert.bytes).map_err(Error::new),
+        tls_api::CertificateFormat::PEM => X509::from_pem(&cert.bytes).map_err(Error::new),
+    }
+}
+
+impl tls_api::TlsConnectorBuilder for ConnectorBuilder {
+    type Connector = Connector;
+    type Underlying = ssl::SslConnectorBuilder;
+
+    fn underlying_mut(&mut self) -> &mut ssl::SslConnectorBuilder {
+        &mut self.builder
+    }
+
+    fn supports_alpn() -> bool {
+        true
+    }
+
+    fn set_alpn_protocols(&mut self, protocols: &[&[u8]]) -> Result<()> {
+        self.builder
+            .set_alpn_protos(&encode_alpn_protocols(protocols)?)
+            .map_err(Error::new)
+    }
+
+    fn set_verify_hostname(&mut self, verify: bool) -> Result<()> {
+        self.verify_hostname = verify;
+        Ok(())
+    }
+
+    fn add_root_certificate(&mut self, cert: tls_api::Certificate) -> Result<&mut Self> {
+        let cert = decode_certificate(&cert)?;
+        self.builder
+            .cert_store_mut()
+            .add_cert(cert)
+            .map_err(Error::new)?;
+        Ok(self)
+    }
+
+    fn build(self) -> Result<Connector> {
+        Ok(Connector {
+            connector: self.builder.build(),
+            verify_hostname: self.verify_hostname,
+            use_sni: self.use_sni,
+        })
+    }
+}
+
+/// The hooks `hyper_tls_api` needs for what tls-api cannot do.
+pub fn client_hooks() -> ClientTlsHooks<ConnectorBuilder> {
+    let mut hooks = ClientTlsHooks::new();
+    hooks.on_set_identity(|builder: &mut ConnectorBuilder, identity| {
+        use_identity(&mut builder.builder, identity)
+    });
+    hooks.on_clear_roots(|builder: &mut ConnectorBuilder| {
+        let store = X509StoreBuilder::new().map_err(Error::new)?;
+        builder.builder.set_cert_store(store.build());
+        Ok(())
+    });
+    hooks.on_disable_sni(|builder: &mut ConnectorBuilder| {
+        builder.use_sni = false;
+        Ok(())
+    });
+    hooks
+}
+
+/// Presents `identity` from a context, checking its key belongs with its
+/// certificate.
+fn use_identity(builder: &mut SslContextBuilder, identity: &hyper_tls_api::Identity) -> Result<()> {
+    let (cert, key, chain) = match *identity {
+        hyper_tls_api::Identity::Pkcs12 {
+            ref der,
+            ref password,
+        } => {
+            let parsed = Pkcs12::from_der(der)
+                .and_then(|p| p.parse2(password))
+                .map_err(Error::new)?;
+            let cert = parsed
+                .cert
+                .ok_or_else(|| Error::new_other("PKCS#12 archive has no certificate"))?;
+            let key = parsed
+                .pkey
+                .ok_or_else(|| Error::new_other("PKCS#12 archive has no private key"))?;
+            let chain = parsed
+                .ca
+                .map(|ca| ca.into_iter().collect())
+                .unwrap_or_default();
+            (cert, key, chain)
+        }
+        hyper_tls_api::Identity::Pem {
+            ref certificate_chain,
+            ref private_key,
+        } => {
+            let mut chain = X509::stack_from_pem(certificate_chain).map_err(Error::new)?;
+            if chain.is_empty() {
+                return Err(Error::new_other("PEM chain has no certificate"));
+            }
+            let cert = chain.remove(0);
+            let key = PKey::private_key_from_pem(private_key).map_err(Error::new)?;
+            (cert, key, chain)
+        }
+    };
+    builder.set_certificate(&cert).map_err(Error::new)?;
+    builder.set_private_key(&key).map_err(Error::new)?;
+    builder.check_private_key().map_err(Error::new)?;
+    for cert in chain {
+        builder.add_extra_chain_cert(cert).map_err(Error::new)?;
+    }
+    Ok(())
+}
+
+impl tls_api::TlsConnector for Connector {
+    type Builder = ConnectorBuilder;
+
+    fn builder() -> Result<ConnectorBuilder> {
+        let builder = SslConnector::builder(SslMethod::tls()).map_err(Error::new)?;
+        Ok(ConnectorBuilder {
+            builder,
+            verify_hostname: true,
+            use_sni: true,
+        })
+    }
+
+    fn connect<S>(
+        &self,
+        domain: &str,
+        stream: S,
+    ) -> result::Result<tls_api::TlsStream<S>, tls_api::HandshakeError<S>>
+    where
+        S: Read + Write + fmt::Debug + Send + Sync + 'static,
+    {
+        self.connector
+            .configure()
+            .map_err(|e| tls_api::HandshakeError::Failure(Error::new(e)))?
+            .verify_hostname(self.verify_hostname)
+            .use_server_name_indication(self.use_sni)
+            .connect(domain, stream)
+            .map(|s| tls_api::TlsStream::new(Stream(s)))
+            .map_err(map_handshake_error)
+    }
+}
+
+impl AcceptorBuilder {
+    pub fn new(identity: &Identity) -> Result<AcceptorBuilder> {
+        let mut builder =
+            SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).map_err(Error::new)?;
+        builder
+            .set_certificate(&identity.cert)
+            .map_err(Error::new)?;
+        builder.set_private_key(&identity.key).map_err(Error::new)?;
+        Ok(AcceptorBuilder {
+            builder,
+            sni: Vec::new(),
+        })
+    }
+}
+
+impl tls_api::TlsAcceptorBuilder for AcceptorBuilder {
+    type Acceptor = Acceptor;
+    type Underlying = ssl::SslAcceptorBuilder;
+
+    fn supports_alpn() -> bool {
+        true
+    }
+
+    fn set_alpn_protocols(&mut self, protocols: &[&[u8]]) -> Result<()> {
+        let protocols = encode_alpn_protocols(protocols)?;
+        self.builder.set_alpn_select_callback(move |_, offered| {
+            let selected = ssl::select_next_proto(&protocols, offered)
+                .ok_or(ssl::AlpnError::NOACK)?
+                .to_vec();
+            // Hand back the client's copy of the name, which outlives the callback.
+            let mut rest = offered;
+            while let Some((&len, tail)) = rest.split_first() {
+                let (name, tail) = tail.split_at(len as usize);
+                if name == &selected[..] {
+                    return Ok(name);
+                }
+                rest = tail;
+            }
+            Err(ssl::AlpnError::NOACK)
+        });
+        Ok(())
+    }
+
+    fn underlying_mut(&mut self) -> &mut ssl::SslAcceptorBuilder {
+        &mut self.builder
+    }
+
+    fn build(mut self) -> Result<Acceptor> {
+        if !self.sni.is_empty() {
+            let sni = self.sni;
+            self.builder.set_servername_callback(move |ssl, _| {
+                let ctx = ssl.servername(NameType::HOST_NAME).and_then(|name| {
+                    sni.iter()
+                        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                        .map(|(_, ctx)| ctx.clone())
+                });
+                if let Some(ctx) = ctx {
+                    ssl.set_ssl_context(&ctx)
+                        .map_err(|_| SniError::ALERT_FATAL)?;
+                }
+                Ok(())
+            });
+        }
+        Ok(Acceptor(self.builder.build()))
+    }
+}
+
+/// An acceptor builder presenting `identity`, the constructor of
+/// `server_hooks`.
+pub fn acceptor_builder(identity: &hyper_tls_api::Identity) -> Result<AcceptorBuilder> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).map_err(Error::new)?;
+    use_identity(&mut builder, identity)?;
+    Ok(AcceptorBuilder {
+        builder,
+        sni: Vec::new(),
+    })
+}
+
+/// The hooks `hyper_tls_api` needs to build acceptors from a config.
+pub fn server_hooks() -> ServerTlsHooks<AcceptorBuilder> {
+    let mut hooks = ServerTlsHooks::new(acceptor_builder);
+    hooks.on_sni_identity(|acceptor: &mut AcceptorBuilder, server_name, identity| {
+        let ctx = acceptor_builder(identity)?.builder.build().into_context();
+        acceptor.sni.push((server_name.to_owned(), ctx));
+        Ok(())
+    });
+    hooks.on_client_auth(|acceptor: &mut AcceptorBuilder, mode, roots| {
+        let mut store = X509StoreBuilder::new().map_err(Error::new)?;
+        for der in roots.certificates() {
+            store
+                .add_cert(X509::from_der(der).map_err(Error::new)?)
+                .map_err(Error::new)?;
+        }
+        acceptor
+            .builder
+            .set_verify_cert_store(store.build())
+            .map_err(Error::new)?;
+        acceptor.builder.set_verify(match mode {
+            ClientAuth::None => SslVerifyMode::NONE,
+            ClientAuth::Optional => SslVerifyMode::PEER,
+            ClientAuth::Required => SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+        });
+        Ok(())
+    });
+    hooks
+}
+
+impl tls_api::TlsAcceptor for Acceptor {
+    type Builder = AcceptorBuilder;
+
+    fn accept<S>(
+        &self,
+        stream: S,
+    ) -> result::Result<tls_api::TlsStream<S>, tls_api::HandshakeError<S>>
+    where
+        S: Read + Write + fmt::Debug + Send + Sync + 'static,
+    {
+        self.0
+            .accept(stream)
+            .map(|s| tls_api::TlsStream::new(Stream(s)))
+            .map_err(map_handshake_error)
+    }
+}
+
+/// A certificate together with its private key.
+#[derive(Clone)]
+pub struct Identity {
+    pub cert: X509,
+    pub key: PKey<Private>,
+}
+
+fn new_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+fn new_certificate(
+    common_name: Option<&str>,
+    key: &PKey<Private>,
+    issuer: Option<&Identity>,
+    names: &[&str],
+    valid: (i64, i64),
+) -> X509 {
+    let mut name = X509NameBuilder::new().unwrap();
+    if let Some(common_name) = common_name {
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+    }
+    let name = name.build();
+
+    let mut serial = BigNum::new().unwrap();
+    serial.rand(64, MsbOption::MAYBE_ZERO, false).unwrap();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&serial.to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_pubkey(key).unwrap();
+    let days_from_now = |days: i64| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Asn1Time::from_unix(now.as_secs() as i64 + days * 24 * 60 * 60).unwrap()
+    };
+    builder.set_not_before(&days_from_now(valid.0)).unwrap();
+    builder.set_not_after(&days_from_now(valid.1)).unwrap();
+
+    match issuer {
+        None => {
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+            builder.set_issuer_name(&name).unwrap();
+            builder.sign(key, MessageDigest::sha256()).unwrap();
+        }
+        Some(issuer) => {
+            let mut san = SubjectAlternativeName::new();
+            for name in names {
+                if name.parse::<::std::net::IpAddr>().is_ok() {
+                    san.ip(name);
+                } else {
+                    san.dns(name);
+                }
+            }
+            let san = san.build(&builder.x509v3_context(Some(&issuer.cert), None));
+            builder.append_extension(san.unwrap()).unwrap();
+            builder.set_issuer_name(issuer.cert.subject_name()).unwrap();
+            builder.sign(&issuer.key, MessageDigest::sha256()).unwrap();
+        }
+    }
+    builder.build()
+}
+
+impl Identity {
+    /// A self-signed certificate authority.
+    pub fn ca() -> Identity {
+        let key = new_key();
+        let cert = new_certificate(Some("hyper-tls-api test CA"), &key, None, &[], (0, 30));
+        Identity { cert, key }
+    }
+
+    /// A leaf certificate issued by `self` for the given DNS names or IPs.
+    pub fn issue(&self, names: &[&str]) -> Identity {
+        self.issue_valid(names, 0, 30)
+    }
+
+    /// Like `issue`, but with a 2048-bit RSA key rather than a P-256 one.
+    pub fn issue_rsa(&self, names: &[&str]) -> Identity {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let common_name = names.first().unwrap_or(&"leaf");
+        let cert = new_certificate(Some(common_name), &key, Some(self), names, (0, 30));
+        Identity { cert, key }
+    }
+
+    /// Like `issue`, but with an empty subject, naming the leaf only by its
+    /// alternative names.
+    pub fn issue_without_subject(&self, names: &[&str]) -> Identity {
+        let key = new_key();
+        let cert = new_certificate(None, &key, Some(self), names, (0, 30));
+        Identity { cert, key }
+    }
+
+    /// Like `issue`, but valid only from `from` days from now until `until`
+    /// days from now; either may be negative.
+    pub fn issue_valid(&self, names: &[&str], from: i64, until: i64) -> Identity {
+        let key = new_key();
+        let common_name = names.first().unwrap_or(&"leaf");
+        let cert = new_certificate(Some(common_name), &key, Some(self), names, (from, until));
+        Identity { cert, key }
+    }
+}
+
+/// A directory of its own in the temporary directory, removed with
+/// everything in it when dropped.
+pub struct TempDir {
+    path: PathBuf,
+    next: Cell<usize>,
+}
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::SeqCst);
+        let path = env::temp_dir().join(format!("hyper-tls-api-{}-{}", process::id(), n));
+        fs::create_dir(&path).unwrap();
+        TempDir {
+            path,
+            next: Cell::new(0),
+        }
+    }
+
+    /// Writes `contents` to a fresh file in the directory.
+    pub fn file(&self, contents: &[u8]) -> PathBuf {
+        let n = self.next.get();
+        self.next.set(n + 1);
+        let path = self.path.join(n.to_string());
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Builds a client connector that trusts `ca`.
+pub fn connector_trusting(ca: &Identity) -> Connector {
+    use tls_api::{TlsConnector, TlsConnectorBuilder};
+
+    let mut builder = Connector::builder().unwrap();
+    builder
+        .add_root_certificate(tls_api::Certificate::from_der(ca.cert.to_der().unwrap()))
+        .unwrap();
+    builder.build().unwrap()
+}
+
+/// Builds an `HttpsConnector` that trusts `ca` and reports what it
+/// verifies.
+pub fn https_connector(ca: &Identity) -> HttpsConnector<HttpConnector, Connector> {
+    use tls_api::TlsConnectorBuilder;
+
+    let ca = ca.cert.to_der().unwrap();
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    let mut hooks = client_hooks();
+    hooks.on_build(move |builder: &mut ConnectorBuilder| {
+        builder.add_root_certificate(tls_api::Certificate::from_der(ca.clone()))?;
+        report_verification(builder);
+        Ok(())
+    });
+    HttpsConnector::with_tls_hooks(http, hooks).unwrap()
+}
+
+/// Reports the chain the connector verifies to `hyper_tls_api`, which makes
+/// the server's certificates visible with TLS 1.3 too, or why OpenSSL
+/// rejected it.
+pub fn report_verification(builder: &mut ConnectorBuilder) {
+    use tls_api::TlsConnectorBuilder;
+
+    builder
+        .underlying_mut()
+        .set_verify_callback(SslVerifyMode::PEER, |ok, ctx| {
+            if !ok {
+                hyper_tls_api::report_verify_error(ctx.error().error_string().to_owned());
+            } else if ctx.error_depth() == 0 {
+                if let Some(chain) = ctx.chain() {
+                    let chain = chain.iter().map(|c| c.to_der().unwrap()).collect();
+                    hyper_tls_api::report_peer_certificates(chain);
+                }
+            }
+            ok
+        });
+}
+
+/// Fetches `uri` through `connector` on a runtime of its own and returns
+/// the body.
+pub fn get<C>(connector: C, uri: &str) -> result::Result<String, hyper::Error>
+where
+    C: Connect + Sync + 'static,
+    C::Transport: 'static,
+    C::Future: 'static,
+{
+    get_on(&mut Runtime::new().unwrap(), connector, uri)
+}
+
+/// Fetches `uri` through `connector` on `rt`, for tests whose server runs
+/// there too.
+pub fn get_on<C>(rt: &mut Runtime, connector: C, uri: &str) -> result::Result<String, hyper::Error>
+where
+    C: Connect + Sync + 'static,
+    C::Transport: 'static,
+    C::Future: 'static,
+{
+    let client = Client::builder().build::<_, Body>(connector);
+    rt.block_on(
+        client
+            .get(uri.parse().unwrap())
+            .and_then(|res| res.into_body().concat2()),
+    )
+    .map(|body| String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// The `io::Error` a connector failed a request with.
+fn connect_io_error(err: hyper::Error) -> io::Error {
+    let cause = err.into_cause().expect("connect error has a cause");
+    *cause
+        .downcast::<io::Error>()
+        .expect("connect error is an io::Error")
+}
+
+/// The crate's error behind a failed request.
+pub fn connect_error(err: hyper::Error) -> hyper_tls_api::Error {
+    hyper_tls_api::Error::from(connect_io_error(err))
+}
+
+/// The host and observed hashes of the pinning error behind a failed
+/// request.
+pub fn pinning_error(err: hyper::Error) -> (String, Vec<SpkiHash>) {
+    match hyper_tls_api::Error::from(connect_io_error(err)) {
+        hyper_tls_api::Error::Pinning(pinning) => {
+            (pinning.host().to_owned(), pinning.observed().to_vec())
+        }
+        other => panic!("expected a pinning error, got {:?}", other),
+    }
+}
+
+/// The phase of the timeout behind a failed request, checking it fails
+/// as `TimedOut`.
+pub fn timeout_phase(err: hyper::Error) -> Phase {
+    let io = connect_io_error(err);
+    assert_eq!(io.kind(), io::ErrorKind::TimedOut);
+    match hyper_tls_api::Error::from(io) {
+        hyper_tls_api::Error::Timeout(timeout) => timeout.phase(),
+        other => panic!("expected a timeout error, got {:?}", other),
+    }
+}
+
+/// Builds a server acceptor presenting `identity`.
+pub fn acceptor(identity: &Identity) -> Acceptor {
+    acceptor_with_alpn(identity, &[])
+}
+
+/// Builds a server acceptor presenting `identity` that selects from
+/// `protocols` through ALPN.
+pub fn acceptor_with_alpn(identity: &Identity, protocols: &[&[u8]]) -> Acceptor {
+    use tls_api::TlsAcceptorBuilder;
+
+    let mut builder = AcceptorBuilder::new(identity).unwrap();
+    if !protocols.is_empty() {
+        builder.set_alpn_protocols(protocols).unwrap();
+    }
+    builder.build().unwrap()
+}
+
+pub const BODY: &str = "hello from the loopback server";
+
+fn respond<S: Read + Write>(mut stream: S) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        BODY.len(),
+        BODY
+    )?;
+    stream.flush()
+}
+
+/// Serves `connections` plain HTTP connections on a loopback port.
+pub fn http_server(connections: usize) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming().take(connections) {
+            let _ = respond(stream.unwrap());
+        }
+    });
+    addr
+}
+
+/// Serves `connections` HTTPS connections on a loopback port, presenting
+/// `identity`. Failed handshakes count towards `connections`.
+pub fn https_server(identity: &Identity, connections: usize) -> SocketAddr {
+    let acceptor = acceptor(identity);
+    https_server_with(acceptor, connections)
+}
+
+/// Like `https_server`, but with a caller-configured acceptor.
+pub fn https_server_with(acceptor: Acceptor, connections: usize) -> SocketAddr {
+    https_server_at("127.0.0.1:0".parse().unwrap(), acceptor, connections)
+}
+
+/// Like `https_server_with`, but listening on `addr`.
+pub fn https_server_at(addr: SocketAddr, acceptor: Acceptor, connections: usize) -> SocketAddr {
+    let listener = TcpListener::bind(addr).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming().take(connections) {
+            let stream: TcpStream = stream.unwrap();
+            if let Ok(stream) = acceptor.0.accept(stream) {
+                let _ = respond(stream);
+            }
+        }
+    });
+    addr
+}
+
+/// An in-process forward proxy, recording the request heads it receives.
+pub struct Proxy {
+    pub addr: SocketAddr,
+    heads: Arc<Mutex<Vec<String>>>,
+}
+
+impl Proxy {
+    /// The request heads received so far.
+    pub fn heads(&self) -> Vec<String> {
+        self.heads.lock().unwrap().clone()
+    }
+}
+
+/// Proxies `connections` connections, tunnelling `CONNECT` requests and
+/// forwarding absolute-form requests.
+pub fn proxy_server(connections: usize) -> Proxy {
+    proxy_server_with(connections, |_| None)
+}
+
+/// Like `proxy_server`, but `refuse` may answer a request head with a status
+/// line such as `"HTTP/1.1 403 Forbidden"` instead of proxying it.
+pub fn proxy_server_with<F>(connections: usize, refuse: F) -> Proxy
+where
+    F: Fn(&str) -> Option<&'static str> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let heads = Arc::new(Mutex::new(Vec::new()));
+    let recorded = heads.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().take(connections) {
+            let mut client = stream.unwrap();
+            let head = match read_head(&mut client) {
+                Ok(head) => head,
+                Err(_) => continue,
+            };
+            recorded.lock().unwrap().push(head.clone());
+            if let Some(status) = refuse(&head) {
+                let _ = write!(client, "{}\r\nContent-Length: 0\r\n\r\n", status);
+                continue;
+            }
+            let _ = forward(client, &head);
+        }
+    });
+    Proxy { addr, heads }
+}
+
+fn read_head<S: Read>(stream: &mut S) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        head.push(byte[0]);
+    }
+    Ok(String::from_utf8(head).unwrap())
+}
+
+fn forward(mut client: TcpStream, head: &str) -> io::Result<()> {
+    let mut request_line = head.split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let target = request_line.next().unwrap_or("");
+    let mut upstream = if method == "CONNECT" {
+        let upstream = TcpStream::connect(target)?;
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+        upstream
+    } else {
+        let authority = target
+            .trim_start_matches("http://")
+            .split('/')
+            .next()
+            .unwrap_or("");
+        let mut upstream = TcpStream::connect(authority)?;
+        upstream.write_all(head.as_bytes())?;
+        upstream
+    };
+    splice(&mut client, &mut upstream)
+}
+
+/// Copies bytes both ways until both sides are done.
+fn splice(a: &mut TcpStream, b: &mut TcpStream) -> io::Result<()> {
+    let (mut a2, mut b2) = (a.try_clone()?, b.try_clone()?);
+    let copy = thread::spawn(move || {
+        let _ = io::copy(&mut a2, &mut b2);
+        let _ = b2.shutdown(Shutdown::Write);
+    });
+    let _ = io::copy(b, a);
+    let _ = a.shutdown(Shutdown::Write);
+    let _ = copy.join();
+    Ok(())
+}
+
+/// An in-process SOCKS5 proxy, recording the destinations it is asked for.
+pub struct SocksProxy {
+    pub addr: SocketAddr,
+    targets: Arc<Mutex<Vec<String>>>,
+}
+
+impl SocksProxy {
+    /// The requested destinations as `host:port`, with names left unresolved.
+    pub fn targets(&self) -> Vec<String> {
+        self.targets.lock().unwrap().clone()
+    }
+}
+
+/// Proxies `connections` SOCKS5 connections, requiring `credentials` if
+/// given.
+pub fn socks_server(connections: usize, credentials: Option<(&str, &str)>) -> SocksProxy {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let targets = Arc::new(Mutex::new(Vec::new()));
+    let recorded = targets.clone();
+    let credentials = credentials.map(|(u, p)| (u.as_bytes().to_vec(), p.as_bytes().to_vec()));
+    thread::spawn(move || {
+        for stream in listener.incoming().take(connections) {
+            let _ = socks_session(stream.unwrap(), credentials.as_ref(), &recorded);
+        }
+    });
+    SocksProxy { addr, targets }
+}
+
+fn read_bytes<S: Read>(stream: &mut S, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn socks_session(
+    mut client: TcpStream,
+    credentials: Option<&(Vec<u8>, Vec<u8>)>,
+    targets: &Mutex<Vec<String>>,
+) -> io::Result<()> {
+    let greeting = read_bytes(&mut client, 2)?;
+    let methods = read_bytes(&mut client, greeting[1] as usize)?;
+    let method = if credentials.is_some() { 2 } else { 0 };
+    if !methods.contains(&method) {
+        return client.write_all(&[5, 0xff]);
+    }
+    client.write_all(&[5, method])?;
+
+    if let Some((user, password)) = credentials {
+        let len = read_bytes(&mut client, 2)?[1] as usize;
+        let given_user = read_bytes(&mut client, len)?;
+        let len = read_bytes(&mut client, 1)?[0] as usize;
+        let given_password = read_bytes(&mut client, len)?;
+        if given_user != *user || given_password != *password {
+            return client.write_all(&[1, 1]);
+        }
+        client.write_all(&[1, 0])?;
+    }
+
+    let request = read_bytes(&mut client, 4)?;
+    let host = match request[3] {
+        1 => {
+            let ip = read_bytes(&mut client, 4)?;
+            format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3])
+        }
+        3 => {
+            let len = read_bytes(&mut client, 1)?[0] as usize;
+            String::from_utf8(read_bytes(&mut client, len)?).unwrap()
+        }
+        _ => return client.write_all(&[5, 8, 0, 1, 0, 0, 0, 0, 0, 0]),
+    };
+    let port = read_bytes(&mut client, 2)?;
+    let target = format!("{}:{}", host, u16::from(port[0]) << 8 | u16::from(port[1]));
+    targets.lock().unwrap().push(target.clone());
+
+    let mut upstream = match TcpStream::connect(&target[..]) {
+        Ok(upstream) => upstream,
+        Err(_) => return client.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]),
+    };
+    client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])?;
+    splice(&mut client, &mut upstream)
+}
+
+/// Like `proxy_server`, but speaking TLS with `identity` to its clients.
+pub fn https_proxy_server(identity: &Identity, connections: usize) -> Proxy {
+    let inner = proxy_server(connections);
+    let acceptor = acceptor_with_alpn(identity, &[b"h2", b"http/1.1"]);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let plain = inner.addr;
+    thread::spawn(move || {
+        for stream in listener.incoming().take(connections) {
+            let stream: TcpStream = stream.unwrap();
+            if let Ok(client) = acceptor.0.accept(stream) {
+                // A real proxy would now expect HTTP/2.
+                if client.ssl().selected_alpn_protocol() == Some(b"h2") {
+                    continue;
+                }
+                if let Ok(upstream) = TcpStream::connect(plain) {
+                    let _ = relay(client, upstream);
+                }
+            }
+        }
+    });
+    Proxy {
+        addr,
+        heads: inner.heads,
+    }
+}
+
+/// Copies bytes both ways between a TLS stream, which cannot be split across
+/// threads, and a plain one, until both sides are done.
+fn relay(mut tls: ssl::SslStream<TcpStream>, mut plain: TcpStream) -> io::Result<()> {
+    use std::time::Duration;
+
+    let poll = Some(Duration::from_millis(5));
+    tls.get_ref().set_read_timeout(poll)?;
+    plain.set_read_timeout(poll)?;
+    let would_block = |e: &io::Error| {
+        e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+    };
+    let mut buf = [0; 16 * 1024];
+    let (mut tls_open, mut plain_open) = (true, true);
+    while tls_open || plain_open {
+        if tls_open {
+            match tls.read(&mut buf) {
+                Ok(0) => {
+                    tls_open = false;
+                    let _ = plain.shutdown(Shutdown::Write);
+                }
+                Ok(n) => plain.write_all(&buf[..n])?,
+                Err(ref e) if would_block(e) => {}
+                Err(_) => tls_open = false,
+            }
+        }
+        if plain_open {
+            match plain.read(&mut buf) {
+                Ok(0) => {
+                    plain_open = false;
+                    let _ = tls.shutdown();
+                }
+                Ok(n) => tls.write_all(&buf[..n])?,
+                Err(ref e) if would_block(e) => {}
+                Err(_) => plain_open = false,
+            }
+        }
+    }
+    Ok(())
+}
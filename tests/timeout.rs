@@ -0,0 +1,86 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper_tls_api::Phase;
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::Duration;
+
+use support::Identity;
+
+/// Accepts connections and holds them open without ever answering.
+fn silent_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut held = Vec::new();
+        for stream in listener.incoming() {
+            held.push(stream);
+        }
+    });
+    addr
+}
+
+#[test]
+fn silent_server_fails_the_handshake_timeout() {
+    let ca = Identity::ca();
+    let addr = silent_server();
+    let mut connector = support::https_connector(&ca);
+    connector.set_handshake_timeout(Some(Duration::from_millis(100)));
+
+    let err = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err();
+    assert_eq!(support::timeout_phase(err), Phase::Handshake);
+}
+
+#[test]
+fn silent_server_fails_the_total_timeout() {
+    let ca = Identity::ca();
+    let addr = silent_server();
+    let mut connector = support::https_connector(&ca);
+    connector.set_timeout(Some(Duration::from_millis(100)));
+
+    let err = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap_err();
+    assert_eq!(support::timeout_phase(err), Phase::Total);
+}
+
+#[test]
+fn silent_proxy_fails_the_connect_timeout() {
+    let ca = Identity::ca();
+    let proxy = silent_server();
+    let mut connector = support::https_connector(&ca);
+    connector
+        .set_proxy(Some(format!("http://{}", proxy).parse().unwrap()))
+        .unwrap();
+    connector.set_connect_timeout(Some(Duration::from_millis(100)));
+    connector.set_handshake_timeout(Some(Duration::from_millis(100)));
+
+    // The proxy accepts the connection but never answers CONNECT.
+    let err = support::get(connector, "https://localhost:1/").unwrap_err();
+    assert_eq!(support::timeout_phase(err), Phase::Connect);
+}
+
+#[test]
+fn timeouts_do_not_fire_on_a_responsive_server() {
+    let ca = Identity::ca();
+    let addr = support::https_server(&ca.issue(&["localhost"]), 1);
+    let mut connector = support::https_connector(&ca);
+    connector.set_connect_timeout(Some(Duration::from_secs(5)));
+    connector.set_handshake_timeout(Some(Duration::from_secs(5)));
+    connector.set_timeout(Some(Duration::from_secs(10)));
+
+    let body = support::get(connector, &format!("https://localhost:{}/", addr.port())).unwrap();
+    assert_eq!(body, support::BODY);
+}
+
+#[test]
+fn timeout_error_names_its_phase() {
+    assert_eq!(Phase::Connect.to_string(), "connect");
+    assert_eq!(Phase::Handshake.to_string(), "TLS handshake");
+    assert_eq!(Phase::Total.to_string(), "overall connection");
+}
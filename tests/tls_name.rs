@@ -0,0 +1,131 @@
+extern crate futures;
+extern crate hyper;
+extern crate hyper_tls_api;
+extern crate openssl;
+extern crate tls_api;
+extern crate tokio;
+
+mod support;
+
+use hyper::client::HttpConnector;
+use hyper_tls_api::HttpsConnector;
+use openssl::ssl::NameType;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tls_api::{TlsAcceptorBuilder, TlsConnectorBuilder};
+
+use support::Identity;
+
+/// Serves `identity` and records the SNI names clients send.
+fn server(identity: &Identity) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let seen = names.clone();
+    let mut builder = support::AcceptorBuilder::new(identity).unwrap();
+    builder
+        .underlying_mut()
+        .set_servername_callback(move |ssl, _| {
+            if let Some(name) = ssl.servername(NameType::HOST_NAME) {
+                seen.lock().unwrap().push(name.to_owned());
+            }
+            Ok(())
+        });
+    (
+        support::https_server_with(builder.build().unwrap(), 1),
+        names,
+    )
+}
+
+#[test]
+fn ip_literal_without_tls_name_fails_verification() {
+    let ca = Identity::ca();
+    let (addr, _) = server(&ca.issue(&["backend.test"]));
+
+    let connector = support::https_connector(&ca);
+    let uri = format!("https://127.0.0.1:{}/", addr.port());
+    let err = support::get(connector, &uri).unwrap_err();
+    assert!(err.is_connect(), "unexpected error: {}", err);
+}
+
+#[test]
+fn tls_name_is_sent_and_verified() {
+    let ca = Identity::ca();
+    let (addr, names) = server(&ca.issue(&["backend.test"]));
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_tls_name("127.0.0.1", addr.port(), "backend.test");
+    let uri = format!("https://127.0.0.1:{}/", addr.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert_eq!(*names.lock().unwrap(), vec!["backend.test".to_owned()]);
+}
+
+#[test]
+fn ip_literal_sni_can_be_left_out() {
+    let ca = Identity::ca();
+    let (addr, names) = server(&ca.issue(&["127.0.0.1"]));
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_ip_literal_sni(false).unwrap();
+    let uri = format!("https://127.0.0.1:{}/", addr.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert!(names.lock().unwrap().is_empty());
+}
+
+#[test]
+fn sni_follows_the_tls_name() {
+    let ca = Identity::ca();
+
+    // An IP address given a name to verify sends it.
+    let (addr, names) = server(&ca.issue(&["backend.test"]));
+    let mut connector = support::https_connector(&ca);
+    connector.set_tls_name("127.0.0.1", addr.port(), "backend.test");
+    connector.set_ip_literal_sni(false).unwrap();
+    let uri = format!("https://127.0.0.1:{}/", addr.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert_eq!(*names.lock().unwrap(), vec!["backend.test".to_owned()]);
+
+    // A name given an IP address to verify leaves it out.
+    let (addr, names) = server(&ca.issue(&["127.0.0.1"]));
+    let mut connector = support::https_connector(&ca);
+    connector.set_tls_name("localhost", addr.port(), "127.0.0.1");
+    connector.set_ip_literal_sni(false).unwrap();
+    let uri = format!("https://localhost:{}/", addr.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert!(names.lock().unwrap().is_empty());
+}
+
+#[test]
+fn named_destinations_keep_sni() {
+    let ca = Identity::ca();
+    let (addr, names) = server(&ca.issue(&["localhost"]));
+
+    let mut connector = support::https_connector(&ca);
+    connector.set_ip_literal_sni(false).unwrap();
+    let uri = format!("https://localhost:{}/", addr.port());
+    assert_eq!(support::get(connector, &uri).unwrap(), support::BODY);
+    assert_eq!(*names.lock().unwrap(), vec!["localhost".to_owned()]);
+}
+
+#[test]
+fn prebuilt_connector_cannot_drop_sni() {
+    let ca = Identity::ca();
+    let mut connector =
+        HttpsConnector::from((HttpConnector::new(1), support::connector_trusting(&ca)));
+    assert!(connector.set_ip_literal_sni(false).is_err());
+}
+
+#[test]
+fn dropping_sni_needs_a_hook() {
+    let ca = Identity::ca();
+    let mut http = HttpConnector::new(1);
+    http.enforce_http(false);
+    let trusted = ca.cert.to_der().unwrap();
+    let mut connector: HttpsConnector<_, support::Connector> =
+        HttpsConnector::with_tls_builder(http, move |builder: &mut support::ConnectorBuilder| {
+            builder.add_root_certificate(tls_api::Certificate::from_der(trusted.clone()))?;
+            Ok(())
+        })
+        .unwrap();
+    let err = connector.set_ip_literal_sni(false).unwrap_err();
+    assert!(err.to_string().contains("on_disable_sni"), "{}", err);
+    assert!(format!("{:?}", connector).contains("ip_literal_sni: true"));
+}